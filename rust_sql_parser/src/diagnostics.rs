@@ -0,0 +1,215 @@
+use crate::{extract_table_name_strings, extract_traced, reader, statement_kind, with_dialect_hint, ExtractOptions, ParseError, TableNameExtractor};
+use fallible_iterator::FallibleIterator;
+use sqlite3_parser::ast::Cmd;
+use sqlite3_parser::lexer::sql::Parser;
+use std::collections::HashSet;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub line: u64,
+    pub column: usize,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{} at line {}, column {}: {}", severity, self.line, self.column, self.message)
+    }
+}
+
+/// One decision taken while extracting tables, for `explain_extraction`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExtractionStep {
+    /// The 1-based statement the decision belongs to.
+    pub statement: usize,
+    /// The enclosing clauses, outermost first, e.g. `SELECT > WHERE > IN`.
+    pub clause: String,
+    /// What happened, e.g. `added t` or `skipped c: CTE`.
+    pub decision: String,
+}
+
+impl fmt::Display for ExtractionStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "statement {}: {}: {}", self.statement, self.clause, self.decision)
+    }
+}
+
+/// Traces how the tables of `sql_query` are found: every table added and
+/// every source skipped as a CTE or table function, in visiting order.
+pub fn explain_extraction(sql_query: &str, options: &ExtractOptions) -> Result<Vec<ExtractionStep>, ParseError> {
+    let extractor = extract_traced(sql_query, options, true)?;
+    Ok(extractor.trace.unwrap_or_default())
+}
+
+/// Extracts the tables of a whole SQL file, collecting a diagnostic for every
+/// statement that contributes no tables because it is not understood. The
+/// first syntax error ends the analysis and is reported as an error; the
+/// tables found up to that point are still returned.
+///
+/// Like `extract_tables`, a file failing because of a statement wrapped in
+/// parentheses is analyzed again without them.
+pub fn extract_with_diagnostics(sql_query: &str) -> (HashSet<String>, Vec<Diagnostic>) {
+    let options = ExtractOptions::default();
+    let (table_names, diagnostics) = diagnose(sql_query, &options);
+    let failed = diagnostics.iter().any(|diagnostic| diagnostic.severity == Severity::Error);
+    match reader::strip_statement_parens(sql_query) {
+        Some(stripped) if failed => diagnose(&stripped, &options),
+        _ => (table_names, diagnostics),
+    }
+}
+
+fn diagnose(sql_query: &str, options: &ExtractOptions) -> (HashSet<String>, Vec<Diagnostic>) {
+    let mut extractor = TableNameExtractor::new(options);
+    let mut diagnostics = Vec::new();
+    let mut parser = Parser::new(sql_query.as_bytes());
+    let mut statement = 0;
+    loop {
+        let (line, column) = statement_start(sql_query, parser.line(), parser.column());
+        match parser.next() {
+            Ok(Some(cmd)) => {
+                statement += 1;
+                if !extractor.extract_table_names_from_cmd(&cmd) {
+                    let (Cmd::Explain(stmt) | Cmd::ExplainQueryPlan(stmt) | Cmd::Stmt(stmt)) = &cmd;
+                    let message = format!("skipped unsupported {} at statement {}", statement_kind(stmt), statement);
+                    diagnostics.push(Diagnostic { severity: Severity::Warning, message, line, column });
+                }
+            },
+            Ok(None) => break,
+            Err(err) => {
+                if let ParseError::SyntaxError { message, line, column } = with_dialect_hint(ParseError::from_sql_error(err, &parser), sql_query) {
+                    let message = format!("{} at statement {}", message, statement + 1);
+                    diagnostics.push(Diagnostic { severity: Severity::Error, message, line, column });
+                }
+                break;
+            },
+        }
+    }
+    (extract_table_name_strings(&extractor.table_names, options), diagnostics)
+}
+
+/// `extract_with_diagnostics` with the warnings as plain messages, such as
+/// "skipped unsupported PRAGMA at statement 2", for callers that only log them.
+/// A syntax error is not a warning and is left out; use
+/// `extract_with_diagnostics` to tell whether extraction stopped early.
+pub fn extract_with_warnings(sql_query: &str) -> (HashSet<String>, Vec<String>) {
+    let (table_names, diagnostics) = extract_with_diagnostics(sql_query);
+    let warnings = diagnostics
+        .into_iter()
+        .filter(|diagnostic| diagnostic.severity == Severity::Warning)
+        .map(|diagnostic| diagnostic.message)
+        .collect();
+    (table_names, warnings)
+}
+
+/// Moves a position following the previous statement past whitespace and
+/// comments to where the next statement starts.
+fn statement_start(sql_query: &str, mut line: u64, mut column: usize) -> (u64, usize) {
+    let line_start: usize = sql_query.split_inclusive('\n').take((line as usize).saturating_sub(1)).map(str::len).sum();
+    let mut rest = sql_query.as_bytes().get(line_start + column.saturating_sub(1)..).unwrap_or_default();
+    let mut advance = |skipped: &[u8]| {
+        for &b in skipped {
+            if b == b'\n' {
+                line += 1;
+                column = 1;
+            } else {
+                column += 1;
+            }
+        }
+    };
+    loop {
+        let skip = match rest {
+            [b, ..] if b.is_ascii_whitespace() => 1,
+            [b'-', b'-', ..] => rest.iter().position(|&b| b == b'\n').map_or(rest.len(), |end| end + 1),
+            [b'/', b'*', ..] => rest.windows(2).skip(2).position(|w| w == b"*/").map_or(rest.len(), |end| end + 4),
+            _ => break,
+        };
+        advance(&rest[..skip]);
+        rest = &rest[skip..];
+    }
+    (line, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diagnostics_mixed_file() {
+        let sql = "SELECT * FROM a;\nPRAGMA foreign_keys = ON;\nBEGIN;\nINSERT INTO b VALUES (1);\nCREATE INDEX i ON b (x);\nCOMMIT;\nSELECT * FROM WHERE;\nSELECT * FROM c;";
+        let (table_names, diagnostics) = extract_with_diagnostics(sql);
+        let mut expected = HashSet::new();
+        expected.insert("a".to_string());
+        expected.insert("b".to_string());
+        assert_eq!(table_names, expected);
+        let summary: Vec<(Severity, &str, u64)> = diagnostics.iter().map(|d| (d.severity, d.message.as_str(), d.line)).collect();
+        assert_eq!(summary, vec![
+            (Severity::Warning, "skipped unsupported PRAGMA at statement 2", 2),
+            (Severity::Warning, "skipped unsupported CREATE INDEX at statement 5", 5),
+            (Severity::Error, "near WHERE, \"None\": syntax error at statement 7", 7),
+        ]);
+    }
+
+    #[test]
+    fn test_diagnostics_like_extract_tables() {
+        let (table_names, diagnostics) = extract_with_diagnostics("(SELECT * FROM t);\nPRAGMA optimize;");
+        assert_eq!(table_names, crate::extract_tables("(SELECT * FROM t)", &ExtractOptions::default()).unwrap());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        let sql = "(SELECT * FROM t);\nSELECT * FROM WHERE";
+        let (table_names, diagnostics) = extract_with_diagnostics(sql);
+        assert_eq!(table_names.len(), 1);
+        assert_eq!((diagnostics[0].severity, diagnostics[0].line), (Severity::Error, 2));
+        let (_, diagnostics) = extract_with_diagnostics("SELECT DISTINCT ON (a) a FROM t");
+        assert!(diagnostics[0].message.ends_with("(DISTINCT ON is PostgreSQL syntax, SQLite only supports plain DISTINCT) at statement 1"));
+    }
+
+    #[test]
+    fn test_diagnostics_position_after_comments() {
+        let sql = "SELECT * FROM a; -- next\n/* a\nblock */  PRAGMA optimize;";
+        let (_, diagnostics) = extract_with_diagnostics(sql);
+        assert_eq!((diagnostics[0].line, diagnostics[0].column), (3, 11));
+    }
+
+    #[test]
+    fn test_extract_with_warnings() {
+        let (table_names, warnings) = extract_with_warnings("SELECT * FROM a;\nPRAGMA optimize;\nANALYZE a");
+        assert_eq!(table_names.len(), 1);
+        assert_eq!(warnings, vec!["skipped unsupported PRAGMA at statement 2", "skipped unsupported ANALYZE at statement 3"]);
+        let (table_names, warnings) = extract_with_warnings("SELECT * FROM a; PRAGMA x; SELECT * FROM WHERE");
+        assert_eq!(table_names.len(), 1);
+        assert_eq!(warnings, vec!["skipped unsupported PRAGMA at statement 2"]);
+    }
+
+    #[test]
+    fn test_diagnostics_clean_file() {
+        let (table_names, diagnostics) = extract_with_diagnostics("SELECT * FROM a; SELECT * FROM b");
+        assert_eq!(table_names.len(), 2);
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_explain_extraction() {
+        let sql = "WITH c AS (SELECT * FROM base) INSERT INTO t SELECT * FROM c WHERE x IN (SELECT y FROM json_each(c.doc), u)";
+        let steps: Vec<String> = explain_extraction(sql, &ExtractOptions::default()).unwrap().iter().map(ToString::to_string).collect();
+        assert_eq!(steps, vec![
+            "statement 1: INSERT > WITH: defined CTE c",
+            "statement 1: INSERT > WITH > FROM: added base",
+            "statement 1: INSERT: added t (written)",
+            "statement 1: INSERT > FROM: skipped c: CTE",
+            "statement 1: INSERT > WHERE > IN > FROM: skipped json_each: table function",
+            "statement 1: INSERT > WHERE > IN > FROM: added u",
+        ]);
+    }
+}
@@ -0,0 +1,241 @@
+use crate::{extract_table_access, extract_tables, rank_counts, ExtractOptions, ParseError, TableAccess};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Comma separated names on one line.
+    #[default]
+    Comma,
+    /// One name per line.
+    Lines,
+    /// A JSON array of names.
+    Json,
+    /// A shell variable assignment, e.g. `TABLES=a,b`.
+    Env,
+    /// A Markdown table of schema, table and read/write access.
+    Markdown,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<OutputFormat, String> {
+        match format {
+            "comma" => Ok(OutputFormat::Comma),
+            "lines" => Ok(OutputFormat::Lines),
+            "json" => Ok(OutputFormat::Json),
+            "env" => Ok(OutputFormat::Env),
+            "markdown" => Ok(OutputFormat::Markdown),
+            _ => Err(format!("Unknown format {}, expected comma, lines, json, env or markdown", format)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct FormatOptions {
+    pub extract: ExtractOptions,
+    pub format: OutputFormat,
+    /// Sort the names instead of printing them in set order.
+    pub sort: bool,
+    /// Joins the names instead of the format's own separator; not used by JSON.
+    pub separator: Option<String>,
+    /// The variable assigned by `OutputFormat::Env`, `TABLES` by default.
+    pub env_var: Option<String>,
+}
+
+/// Extracts the tables of `sql_query` and formats them exactly like the CLI.
+pub fn parse_and_format(sql_query: &str, options: &FormatOptions) -> Result<String, ParseError> {
+    if options.format == OutputFormat::Markdown {
+        let access = extract_table_access(sql_query, &options.extract)?;
+        return Ok(format_table_access(&access, options));
+    }
+    let table_names = extract_tables(sql_query, &options.extract)?;
+    Ok(format_tables(&table_names, options))
+}
+
+pub fn format_tables(table_names: &HashSet<String>, options: &FormatOptions) -> String {
+    format_names(table_names.iter().map(String::as_str).collect(), options)
+}
+
+/// Formats `table_names` like `format_tables`, keeping their order unless
+/// sorting and any name listed more than once, e.g. once per reference.
+pub fn format_table_list(table_names: &[String], options: &FormatOptions) -> String {
+    format_names(table_names.iter().map(String::as_str).collect(), options)
+}
+
+fn format_names(mut names: Vec<&str>, options: &FormatOptions) -> String {
+    if options.sort {
+        names.sort();
+    }
+    let separator = |default| options.separator.as_deref().unwrap_or(default);
+    match options.format {
+        OutputFormat::Comma => names.join(separator(",")),
+        OutputFormat::Lines => names.join(separator("\n")),
+        OutputFormat::Json => {
+            let names: Vec<String> = names.iter().map(|name| json_string(name)).collect();
+            format!("[{}]", names.join(","))
+        },
+        OutputFormat::Env => {
+            let var = options.env_var.as_deref().unwrap_or("TABLES");
+            format!("{}={}", var, shell_word(&names.join(separator(","))))
+        },
+        // without the access of each table, that column is left empty
+        OutputFormat::Markdown => markdown_table(names.into_iter().map(|name| (name, false, false))),
+    }
+}
+
+/// Formats the tables of `access` as a Markdown table with a row per table
+/// saying whether it is read, written or both. Other formats than
+/// `OutputFormat::Markdown` list the tables the same way `format_tables` does.
+pub fn format_table_access(access: &TableAccess, options: &FormatOptions) -> String {
+    if options.format != OutputFormat::Markdown {
+        return format_tables(&access.reads.union(&access.writes).cloned().collect(), options);
+    }
+    let mut names: Vec<&str> = access.reads.union(&access.writes).map(String::as_str).collect();
+    if options.sort {
+        names.sort();
+    }
+    markdown_table(names.into_iter().map(|name| (name, access.reads.contains(name), access.writes.contains(name))))
+}
+
+/// Formats a row per table, given with whether it is read and written.
+/// Unqualified tables are shown under `main`, in the same row as the tables
+/// qualified with `main`, whose access they add to.
+fn markdown_table<'a>(tables: impl Iterator<Item = (&'a str, bool, bool)>) -> String {
+    let mut rows: Vec<((&str, &str), bool, bool)> = Vec::new();
+    for (name, read, written) in tables {
+        let schema_table = name.split_once('\x1F').unwrap_or(("main", name));
+        match rows.iter_mut().find(|(row, _, _)| *row == schema_table) {
+            Some((_, row_read, row_written)) => {
+                *row_read |= read;
+                *row_written |= written;
+            },
+            None => rows.push((schema_table, read, written)),
+        }
+    }
+    let mut markdown = String::from("| Schema | Table | Access |\n| --- | --- | --- |");
+    for ((schema, table), read, written) in rows {
+        let access = match (read, written) {
+            (true, true) => "read/write",
+            (false, true) => "write",
+            (true, false) => "read",
+            (false, false) => "",
+        };
+        let cell = |value: &str| value.replace('|', "\\|");
+        markdown.push_str(&format!("\n| {} | {} | {} |", cell(schema), cell(table), access));
+    }
+    markdown
+}
+
+/// Quotes `value` for a POSIX shell unless it is made of characters that
+/// never need quoting.
+fn shell_word(value: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "_,.-/:@%+".contains(c);
+    if value.chars().all(plain) {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+}
+
+/// Counts the distinct tables of each schema, most tables first and ties in
+/// name order. Unqualified tables are counted under `default_schema`, once
+/// with the same table qualified by it, so `a` and `main.a` count once.
+pub fn count_per_schema(table_names: &HashSet<String>, default_schema: &str) -> Vec<(String, usize)> {
+    let tables: HashSet<(&str, &str)> = table_names
+        .iter()
+        .map(|name| name.split_once('\x1F').unwrap_or((default_schema, name)))
+        .collect();
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for (schema, _) in tables {
+        *counts.entry(schema.to_string()).or_default() += 1;
+    }
+    rank_counts(counts)
+}
+
+fn json_string(value: &str) -> String {
+    let mut json = String::with_capacity(value.len() + 2);
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SQL_QUERY: &str = "SELECT * FROM b JOIN a JOIN ext.\"c\"";
+
+    fn options(format: OutputFormat) -> FormatOptions {
+        FormatOptions { format, sort: true, ..FormatOptions::default() }
+    }
+
+    #[test]
+    fn test_format_comma() {
+        assert_eq!(parse_and_format(SQL_QUERY, &options(OutputFormat::Comma)).unwrap(), "a,b,ext\x1F\"c\"");
+    }
+
+    #[test]
+    fn test_format_lines() {
+        assert_eq!(parse_and_format(SQL_QUERY, &options(OutputFormat::Lines)).unwrap(), "a\nb\next\x1F\"c\"");
+    }
+
+    #[test]
+    fn test_format_json() {
+        assert_eq!(parse_and_format(SQL_QUERY, &options(OutputFormat::Json)).unwrap(), r#"["a","b","ext\u001f\"c\""]"#);
+    }
+
+    #[test]
+    fn test_format_env() {
+        assert_eq!(parse_and_format("SELECT * FROM b JOIN a", &options(OutputFormat::Env)).unwrap(), "TABLES=a,b");
+        let options = FormatOptions { separator: Some(" ".to_string()), env_var: Some("USED".to_string()), ..options(OutputFormat::Env) };
+        assert_eq!(parse_and_format("SELECT * FROM b JOIN \"it's\"", &options).unwrap(), "USED='\"it'\\''s\" b'");
+    }
+
+    #[test]
+    fn test_format_table_list() {
+        let names = vec!["b".to_string(), "a".to_string(), "b".to_string()];
+        assert_eq!(format_table_list(&names, &FormatOptions::default()), "b,a,b");
+        assert_eq!(format_table_list(&names, &options(OutputFormat::Json)), r#"["a","b","b"]"#);
+    }
+
+    #[test]
+    fn test_format_markdown() {
+        let sql_query = "INSERT INTO ext.log SELECT * FROM b JOIN ext.log; UPDATE a SET x = 1";
+        assert_eq!(parse_and_format(sql_query, &options(OutputFormat::Markdown)).unwrap(), "\
+| Schema | Table | Access |
+| --- | --- | --- |
+| main | a | write |
+| main | b | read |
+| ext | log | read/write |");
+        let sql_query = "INSERT INTO main.a SELECT * FROM a JOIN main.b JOIN b";
+        assert_eq!(parse_and_format(sql_query, &options(OutputFormat::Markdown)).unwrap(), "\
+| Schema | Table | Access |
+| --- | --- | --- |
+| main | a | read/write |
+| main | b | read |");
+    }
+
+    #[test]
+    fn test_count_per_schema() {
+        let table_names = crate::extract_tables("SELECT * FROM a, b, ext.c, main.d, aux.e, aux.f", &ExtractOptions::default()).unwrap();
+        let expected = vec![("main".to_string(), 3), ("aux".to_string(), 2), ("ext".to_string(), 1)];
+        assert_eq!(count_per_schema(&table_names, "main"), expected);
+        let expected = vec![("(none)".to_string(), 2), ("aux".to_string(), 2), ("ext".to_string(), 1), ("main".to_string(), 1)];
+        assert_eq!(count_per_schema(&table_names, "(none)"), expected);
+        let table_names = crate::extract_tables("SELECT * FROM main.a; SELECT * FROM a", &ExtractOptions::default()).unwrap();
+        assert_eq!(count_per_schema(&table_names, "main"), vec![("main".to_string(), 1)]);
+    }
+}
@@ -0,0 +1,100 @@
+use crate::{qualified_name_string, ExtractOptions};
+use regex::Regex;
+use sqlite3_parser::ast::{Name, QualifiedName};
+use std::collections::HashSet;
+
+/// Matches routing hints such as `/*+ table=foo */`.
+pub const DEFAULT_HINT_PATTERN: &str = r"\btable=([A-Za-z_][A-Za-z0-9_.]*)";
+
+/// Collects table names from comments matching `pattern`, taking the first
+/// capture group when there is one and the whole match otherwise. A hint
+/// such as `ext.bar` names the table `bar` of schema `ext`, and hints are
+/// normalized with `options` like parsed names so that the two merge.
+///
+/// This is a best-effort heuristic: hints are plain text that the SQL
+/// grammar never sees, so nothing checks that the names exist or are used.
+pub fn scan_comment_hints(sql_query: &str, pattern: &Regex, options: &ExtractOptions) -> HashSet<String> {
+    let mut table_names = HashSet::new();
+    for comment in comments(sql_query) {
+        for captures in pattern.captures_iter(comment) {
+            if let Some(name) = captures.get(1).or_else(|| captures.get(0)) {
+                let qualified_name = match name.as_str().split_once('.') {
+                    Some((schema, table)) => QualifiedName::fullname(Name(schema.to_string()), Name(table.to_string())),
+                    None => QualifiedName::single(Name(name.as_str().to_string())),
+                };
+                table_names.insert(qualified_name_string(&qualified_name, options));
+            }
+        }
+    }
+    table_names
+}
+
+/// Returns the text of the `--` and `/* */` comments of `sql_query`, skipping
+/// string literals and quoted identifiers.
+fn comments(sql_query: &str) -> Vec<&str> {
+    let mut comments = Vec::new();
+    let mut rest = sql_query;
+    while let Some(start) = rest.find(['-', '/', '\'', '"', '`', '[']) {
+        let after = &rest[start + 1..];
+        let (comment, end) = if rest[start..].starts_with("--") {
+            let end = after.find('\n').map_or(rest.len(), |end| start + 1 + end);
+            (Some(&rest[start + 2..end]), end)
+        } else if rest[start..].starts_with("/*") {
+            let end = after[1..].find("*/").map_or(rest.len(), |end| start + 2 + end);
+            (Some(&rest[start + 2..end]), (end + 2).min(rest.len()))
+        } else {
+            let quote = match rest.as_bytes()[start] {
+                b'[' => ']',
+                b'-' | b'/' => {
+                    rest = after;
+                    continue;
+                },
+                quote => quote as char,
+            };
+            // a doubled quote is an escaped quote, which scans as two literals
+            (None, after.find(quote).map_or(rest.len(), |end| start + 2 + end))
+        };
+        comments.extend(comment);
+        rest = &rest[end..];
+    }
+    comments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hints(sql: &str) -> HashSet<String> {
+        scan_comment_hints(sql, &Regex::new(DEFAULT_HINT_PATTERN).unwrap(), &ExtractOptions::default())
+    }
+
+    #[test]
+    fn test_hints_in_comments() {
+        let sql = "SELECT /*+ table=foo */ * FROM a -- table=ext.bar\nWHERE x = 'table=lit' AND \"table=id\" - 1";
+        let mut expected = HashSet::new();
+        expected.insert("foo".to_string());
+        expected.insert("ext\x1Fbar".to_string());
+        assert_eq!(hints(sql), expected);
+    }
+
+    #[test]
+    fn test_hints_normalized() {
+        let options = ExtractOptions { normalize_case: true, normalize_schema: true, ..ExtractOptions::default() };
+        let sql = "SELECT /*+ table=ext.Bar table=MAIN.Foo */ * FROM ext.Bar";
+        let mut expected = HashSet::new();
+        expected.insert("ext\x1Fbar".to_string());
+        expected.insert("foo".to_string());
+        assert_eq!(scan_comment_hints(sql, &Regex::new(DEFAULT_HINT_PATTERN).unwrap(), &options), expected);
+        assert!(crate::extract_tables(sql, &options).unwrap().is_subset(&expected));
+    }
+
+    #[test]
+    fn test_hints_custom_pattern() {
+        let pattern = Regex::new(r"route:(\w+)").unwrap();
+        let sql = "/* route:shard1 table=foo */ SELECT 1 /* unterminated route:shard2";
+        let mut expected = HashSet::new();
+        expected.insert("shard1".to_string());
+        expected.insert("shard2".to_string());
+        assert_eq!(scan_comment_hints(sql, &pattern, &ExtractOptions::default()), expected);
+    }
+}
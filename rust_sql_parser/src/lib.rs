@@ -0,0 +1,2144 @@
+use fallible_iterator::FallibleIterator;
+use sqlite3_parser::dialect::{keyword_token, TokenType};
+use sqlite3_parser::lexer::sql::Tokenizer;
+use sqlite3_parser::lexer::Scanner;
+use sqlite3_parser::lexer::sql::{Error as SqlError, Parser};
+use sqlite3_parser::ast::{As, Cmd, ColumnConstraint, CreateTableBody, DistinctNames, Expr, FromClause, FunctionTail, InsertBody, JoinConstraint, Limit, Name, OneSelect, Over, QualifiedName, ResultColumn, Select, SelectBody, SelectTable, Set, SortedColumn, Stmt, TableConstraint, TriggerCmd, Upsert, UpsertDo, Window, With};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+mod diagnostics;
+mod format;
+mod hints;
+mod reader;
+
+pub use diagnostics::{explain_extraction, extract_with_diagnostics, extract_with_warnings, Diagnostic, ExtractionStep, Severity};
+pub use format::{count_per_schema, format_table_access, format_table_list, format_tables, parse_and_format, FormatOptions, OutputFormat};
+pub use hints::{scan_comment_hints, DEFAULT_HINT_PATTERN};
+pub use reader::{extract_from_reader, StatementReader};
+
+/// Table-valued functions and function-like sources that never name a real
+/// table. Matched case-insensitively; any `pragma_*` source is also treated
+/// as a function.
+pub const DEFAULT_TABLE_FUNCTIONS: &[&str] = &[
+    "json_each",
+    "json_tree",
+    "jsonb_each",
+    "jsonb_tree",
+    "generate_series",
+    "carray",
+    "dbstat",
+    "sqlite_dbpage",
+    "sqlite_stmt",
+    "bytecode",
+    "tables_used",
+    "fsdir",
+    "zipfile",
+    "changes",
+    "total_changes",
+];
+
+/// SQLite's names for the implicit row identifier of a rowid table.
+pub const ROWID_ALIASES: &[&str] = &["rowid", "oid", "_rowid_"];
+
+#[derive(Clone, Debug)]
+pub struct ExtractOptions {
+    /// Report table-valued function sources (e.g. `json_each(...)`) as tables.
+    pub include_functions: bool,
+    /// Reject inputs longer than this many bytes before parsing them.
+    pub max_input_bytes: Option<usize>,
+    /// Lower-case the ASCII letters of reported names so that differently cased
+    /// references match; like SQLite, other letters are not folded.
+    pub normalize_case: bool,
+    /// Drop the `main` schema so that `main.t` and `t` are reported as `t`.
+    pub normalize_schema: bool,
+    /// Report `"t"`, `` `t` ``, `[t]` and `'t'` as `t`.
+    pub strip_quotes: bool,
+    /// Report tables and views created with `TEMP` under the `temp` schema,
+    /// where SQLite creates them.
+    pub qualify_temp: bool,
+    /// Escape `\` and the `\x1F` separator inside each part of a name, so that
+    /// names can be taken apart again with `split_table_name`.
+    pub escape_separator: bool,
+    /// Fail with `ParseError::Unsupported` instead of skipping statements the
+    /// extractor does not understand.
+    pub fail_on_unsupported: bool,
+    /// Functions whose calls in a FROM clause are not tables, matched
+    /// case-insensitively. Defaults to `DEFAULT_TABLE_FUNCTIONS`; add the
+    /// table-valued functions of loadable extensions here.
+    pub table_functions: HashSet<String>,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        ExtractOptions {
+            include_functions: false,
+            max_input_bytes: None,
+            normalize_case: false,
+            normalize_schema: false,
+            strip_quotes: false,
+            qualify_temp: false,
+            escape_separator: false,
+            fail_on_unsupported: false,
+            table_functions: DEFAULT_TABLE_FUNCTIONS.iter().map(|function| function.to_string()).collect(),
+        }
+    }
+}
+
+impl ExtractOptions {
+    /// Returns true if `name` is one of `table_functions` or a `pragma_*` function.
+    pub fn is_table_function(&self, name: &Name) -> bool {
+        self.table_functions.iter().any(|function| name == function.as_str())
+            || name.0.to_ascii_lowercase().starts_with("pragma_")
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    /// The input is not valid SQLite SQL.
+    SyntaxError { message: String, line: u64, column: usize },
+    /// The input is longer than `ExtractOptions::max_input_bytes`.
+    InputTooLarge { size: usize, limit: usize },
+    /// A statement the extractor does not understand, e.g. `PRAGMA`, with
+    /// `ExtractOptions::fail_on_unsupported` set.
+    Unsupported { statement: String },
+    /// Reading the input failed.
+    Io { message: String },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::SyntaxError { message, line, column } => {
+                write!(f, "syntax error at line {}, column {}: {}", line, column, message)
+            },
+            ParseError::InputTooLarge { size, limit } => {
+                write!(f, "input of {} bytes exceeds the limit of {} bytes", size, limit)
+            },
+            ParseError::Unsupported { statement } => write!(f, "unsupported {} statement", statement),
+            ParseError::Io { message } => write!(f, "failed to read the input: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Unwraps the `ParseError` of a `StatementReader` error, e.g. its
+/// `InputTooLarge`; any other read error becomes `ParseError::Io`.
+impl From<std::io::Error> for ParseError {
+    fn from(err: std::io::Error) -> ParseError {
+        match err.get_ref().and_then(|inner| inner.downcast_ref::<ParseError>()) {
+            Some(err) => err.clone(),
+            None => ParseError::Io { message: err.to_string() },
+        }
+    }
+}
+
+impl ParseError {
+    fn from_sql_error(err: SqlError, parser: &Parser) -> ParseError {
+        let (message, position) = match err {
+            SqlError::ParserError(err, position) => (err.to_string(), position),
+            SqlError::UnrecognizedToken(position) => ("unrecognized token".to_string(), position),
+            SqlError::UnterminatedLiteral(position) => ("non-terminated literal".to_string(), position),
+            SqlError::UnterminatedBracket(position) => ("non-terminated bracket".to_string(), position),
+            SqlError::UnterminatedBlockComment(position) => ("non-terminated block comment".to_string(), position),
+            SqlError::BadVariableName(position) => ("bad variable name".to_string(), position),
+            SqlError::BadNumber(position) => ("bad number".to_string(), position),
+            SqlError::ExpectedEqualsSign(position) => ("expected = sign".to_string(), position),
+            SqlError::MalformedBlobLiteral(position) => ("malformed blob literal".to_string(), position),
+            SqlError::MalformedHexInteger(position) => ("malformed hex integer".to_string(), position),
+            SqlError::Io(err) => (err.to_string(), None),
+            _ => ("invalid SQL".to_string(), None),
+        };
+        let (line, column) = position.unwrap_or((parser.line(), parser.column()));
+        ParseError::SyntaxError { message, line, column }
+    }
+}
+
+pub fn parse_sql_command_for_table_names(sql_query: &str) -> HashSet<std::string::String> {
+    extract_tables(sql_query, &ExtractOptions::default()).unwrap_or_default()
+}
+
+/// Extracts the tables referenced by every statement in `sql_query`.
+pub fn extract_tables(sql_query: &str, options: &ExtractOptions) -> Result<HashSet<String>, ParseError> {
+    let extractor = extract(sql_query, options)?;
+    Ok(extract_table_name_strings(&extractor.table_names, options))
+}
+
+/// Maps each table alias in `sql_query` to the table it stands for. Aliases
+/// written with and without `AS` are treated the same; aliases of derived
+/// tables are not included since they do not name a table. An alias reused in
+/// another scope or statement maps to the table it was last given, e.g.
+/// `x -> b` for `SELECT * FROM a x; SELECT * FROM b x`.
+pub fn extract_aliases(sql_query: &str, options: &ExtractOptions) -> Result<HashMap<String, String>, ParseError> {
+    let extractor = extract(sql_query, options)?;
+    Ok(extractor.aliases)
+}
+
+/// Extracts the column references in `sql_query`, written `column` or
+/// `table\x1Fcolumn` (`schema\x1Ftable\x1Fcolumn`) as qualified in the query.
+/// Row identifiers (`rowid`, `oid`, `_rowid_`) are not columns and are left out.
+/// Each part is normalized with the same options as table names.
+pub fn extract_columns(sql_query: &str, options: &ExtractOptions) -> Result<HashSet<String>, ParseError> {
+    let extractor = extract(sql_query, options)?;
+    Ok(extractor.columns)
+}
+
+/// Every identifier of `sql_query` for seeding autocompletion: the schema
+/// and table names of table references, the parts of column references,
+/// CTE names, and table, derived table and result column aliases. This is an
+/// unclassified superset of `extract_tables`, `extract_columns` and
+/// `extract_aliases`.
+pub fn all_identifiers(sql_query: &str, options: &ExtractOptions) -> Result<HashSet<String>, ParseError> {
+    let extractor = extract(sql_query, options)?;
+    let tables = extract_table_name_strings(&extractor.table_names, options);
+    let qualified_names = tables.iter().chain(&extractor.columns);
+    let mut identifiers: HashSet<String> = qualified_names.flat_map(|name| name.split('\x1F')).map(str::to_string).collect();
+    identifiers.extend(extractor.aliases.into_keys());
+    identifiers.extend(extractor.cte_sources.iter().map(|cte| name_part(&name_string(&cte.name), options)));
+    identifiers.extend(extractor.other_aliases);
+    Ok(identifiers)
+}
+
+/// A canonical form of the tables referenced by `sql_query`, suitable as a
+/// cache key: every table is written `schema\x1Ftable` (unqualified tables
+/// belong to `main`), unquoted, lower-cased, sorted and joined with `\x1E`.
+pub fn table_signature(sql_query: &str, options: &ExtractOptions) -> Result<String, ParseError> {
+    let extractor = extract(sql_query, options)?;
+    let canonical = ExtractOptions { strip_quotes: true, normalize_case: true, escape_separator: options.escape_separator, ..ExtractOptions::default() };
+    let part = |name: &Name| name_part(&name_string(name), &canonical);
+    let mut names: Vec<String> = extractor
+        .table_names
+        .iter()
+        .map(|qn| {
+            let schema = qn.db_name.as_ref().map_or_else(|| "main".to_string(), part);
+            format!("{}\x1F{}", schema, part(&qn.name))
+        })
+        .collect();
+    names.sort();
+    names.dedup();
+    Ok(names.join("\x1E"))
+}
+
+/// The tables a query reads from and the tables it modifies or creates.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TableAccess {
+    pub reads: HashSet<String>,
+    pub writes: HashSet<String>,
+}
+
+/// Splits the tables of `sql_query` into reads and writes. The targets of
+/// `INSERT`, `UPDATE`, `DELETE`, `CREATE TABLE` and `CREATE VIEW` are
+/// writes; a table that is also queried, e.g. in
+/// `INSERT INTO t SELECT * FROM t`, is in both sets.
+pub fn extract_table_access(sql_query: &str, options: &ExtractOptions) -> Result<TableAccess, ParseError> {
+    let extractor = extract(sql_query, options)?;
+    let names = |indexes: &[usize]| indexes.iter().map(|&index| qualified_name_string(&extractor.table_names[index], options)).collect();
+    Ok(TableAccess { reads: names(&extractor.read_tables), writes: names(&extractor.written_tables) })
+}
+
+/// How the tables of a query change between two versions of it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TableDiff {
+    pub added: HashSet<String>,
+    pub removed: HashSet<String>,
+    pub common: HashSet<String>,
+}
+
+/// Compares the tables of `before` and `after`. Set `normalize_case` and
+/// `normalize_schema` so that spelling changes are not reported as changes.
+pub fn table_diff(before: &str, after: &str, options: &ExtractOptions) -> Result<TableDiff, ParseError> {
+    let before = extract_tables(before, options)?;
+    let after = extract_tables(after, options)?;
+    Ok(TableDiff {
+        added: after.difference(&before).cloned().collect(),
+        removed: before.difference(&after).cloned().collect(),
+        common: before.intersection(&after).cloned().collect(),
+    })
+}
+
+/// Why `assert_tables_eq` failed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TablesMismatch {
+    Parse(ParseError),
+    /// An expected name that is not a `table` or `schema.table` name.
+    InvalidName(String),
+    /// The sorted `schema.table` or `table` names on either side of the mismatch.
+    Differ { missing: Vec<String>, unexpected: Vec<String> },
+}
+
+impl fmt::Display for TablesMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TablesMismatch::Parse(err) => write!(f, "{}", err),
+            TablesMismatch::InvalidName(name) => write!(f, "expected table {:?} is not a table name", name),
+            TablesMismatch::Differ { missing, unexpected } => {
+                write!(f, "tables differ: missing [{}], unexpected [{}]", missing.join(", "), unexpected.join(", "))
+            },
+        }
+    }
+}
+
+impl std::error::Error for TablesMismatch {}
+
+impl From<ParseError> for TablesMismatch {
+    fn from(err: ParseError) -> TablesMismatch {
+        TablesMismatch::Parse(err)
+    }
+}
+
+/// Checks that `sql_query` references exactly the `expected` tables, written
+/// as in SQL, e.g. `t`, `"T"` or `ext.t`. Case, quotes and the `main` schema
+/// are ignored on both sides.
+pub fn assert_tables_eq(sql_query: &str, expected: &[&str]) -> Result<(), TablesMismatch> {
+    let options = ExtractOptions { normalize_case: true, normalize_schema: true, strip_quotes: true, ..ExtractOptions::default() };
+    let actual = extract_tables(sql_query, &options)?;
+    let mut expected_tables = HashSet::new();
+    for name in expected {
+        let qualified_name = parse_qualified_name(name).ok_or_else(|| TablesMismatch::InvalidName(name.to_string()))?;
+        expected_tables.insert(qualified_name_string(&qualified_name, &options));
+    }
+    let sorted = |names: std::collections::hash_set::Difference<'_, String, _>| {
+        let mut names: Vec<String> = names.map(|name| name.replace('\x1F', ".")).collect();
+        names.sort();
+        names
+    };
+    let missing = sorted(expected_tables.difference(&actual));
+    let unexpected = sorted(actual.difference(&expected_tables));
+    if missing.is_empty() && unexpected.is_empty() {
+        Ok(())
+    } else {
+        Err(TablesMismatch::Differ { missing, unexpected })
+    }
+}
+
+/// Parses `name` or `schema.name`, each part an identifier, a keyword or a
+/// quoted name, and nothing else.
+fn parse_qualified_name(text: &str) -> Option<QualifiedName> {
+    let mut scanner = Scanner::new(Tokenizer::new());
+    let mut parts = Vec::new();
+    let mut dot = false;
+    loop {
+        match scanner.scan(text.as_bytes()) {
+            Ok((_, Some((_, TokenType::TK_DOT)), _)) if !dot && parts.len() == 1 => dot = true,
+            Ok((_, Some((token, token_type)), _)) if parts.len() == usize::from(dot) => {
+                let is_name = matches!(token_type, TokenType::TK_ID | TokenType::TK_STRING)
+                    || (keyword_token(token).is_some() && token.iter().all(u8::is_ascii_alphabetic));
+                if !is_name {
+                    return None;
+                }
+                parts.push(Name(String::from_utf8_lossy(token).into_owned()));
+            },
+            Ok((_, None, _)) => break,
+            _ => return None,
+        }
+    }
+    let table = parts.pop()?;
+    match parts.pop() {
+        Some(schema) => Some(QualifiedName::fullname(schema, table)),
+        None if !dot => Some(QualifiedName::single(table)),
+        None => None,
+    }
+}
+
+/// A common table expression and the tables its query reads.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CteResolution {
+    /// The 1-based statement defining the CTE.
+    pub statement: usize,
+    pub name: String,
+    pub tables: HashSet<String>,
+}
+
+/// Maps each common table expression of `sql_query` to the tables its query
+/// reads, directly or through other CTEs, e.g. `recent -> {events}` for
+/// `WITH recent AS (SELECT * FROM events) SELECT * FROM recent`. Every
+/// definition is resolved in its own scope, but definitions sharing a name
+/// share an entry; `cte_definitions` keeps them apart.
+pub fn cte_resolution(sql_query: &str, options: &ExtractOptions) -> Result<HashMap<String, HashSet<String>>, ParseError> {
+    let mut resolution: HashMap<String, HashSet<String>> = HashMap::new();
+    for cte in cte_definitions(sql_query, options)? {
+        resolution.entry(cte.name).or_default().extend(cte.tables);
+    }
+    Ok(resolution)
+}
+
+/// Resolves each common table expression of `sql_query` like `cte_resolution`,
+/// with one entry per definition so that CTEs sharing a name in different
+/// statements or scopes stay apart. Outer scopes come before nested ones.
+pub fn cte_definitions(sql_query: &str, options: &ExtractOptions) -> Result<Vec<CteResolution>, ParseError> {
+    let extractor = extract(sql_query, options)?;
+    let mut resolution: Vec<CteResolution> = extractor
+        .cte_sources
+        .iter()
+        .map(|cte| CteResolution {
+            statement: cte.statement,
+            name: name_part(&name_string(&cte.name), options),
+            tables: extract_table_name_strings(&cte.tables, options),
+        })
+        .collect();
+    // a CTE reads everything the CTEs it reads do, until nothing changes
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (index, cte) in extractor.cte_sources.iter().enumerate() {
+            for &other in &cte.ctes {
+                let tables: Vec<String> = resolution[other].tables.difference(&resolution[index].tables).cloned().collect();
+                if !tables.is_empty() {
+                    resolution[index].tables.extend(tables);
+                    changed = true;
+                }
+            }
+        }
+    }
+    Ok(resolution)
+}
+
+/// Returns true if a table-valued function call in `sql_query`, listed in
+/// `ExtractOptions::table_functions` or not, takes a bound parameter
+/// or a subquery as an argument, so that the rows it produces depend on
+/// runtime values rather than only on the tables read.
+pub fn has_dynamic_source(sql_query: &str, options: &ExtractOptions) -> Result<bool, ParseError> {
+    let extractor = extract(sql_query, options)?;
+    Ok(extractor.dynamic_source)
+}
+
+/// Counts the joins of each statement of `sql_query` at every nesting level,
+/// comma joins included, e.g. `[2]` for
+/// `SELECT * FROM a, b WHERE x IN (SELECT x FROM c JOIN d)`.
+pub fn join_count(sql_query: &str, options: &ExtractOptions) -> Result<Vec<usize>, ParseError> {
+    let extractor = extract(sql_query, options)?;
+    Ok(extractor.joins)
+}
+
+/// The tables of `sql_query` with how often each is referenced, most
+/// referenced first and ties in name order.
+pub fn ranked_tables(sql_query: &str, options: &ExtractOptions) -> Result<Vec<(String, usize)>, ParseError> {
+    let extractor = extract(sql_query, options)?;
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for &index in &extractor.references {
+        *counts.entry(qualified_name_string(&extractor.table_names[index], options)).or_default() += 1;
+    }
+    Ok(rank_counts(counts))
+}
+
+/// Orders reference counts like `ranked_tables`, most referenced first and
+/// ties in name order.
+pub fn rank_counts(counts: HashMap<String, usize>) -> Vec<(String, usize)> {
+    let mut ranked: Vec<(String, usize)> = counts.into_iter().collect();
+    ranked.sort_by(|(name, count), (other_name, other_count)| other_count.cmp(count).then_with(|| name.cmp(other_name)));
+    ranked
+}
+
+/// Every table reference of `sql_query` in the order the statements and their
+/// clauses are visited, e.g. `b, a, b` for `SELECT * FROM b JOIN a JOIN b`.
+pub fn table_references(sql_query: &str, options: &ExtractOptions) -> Result<Vec<String>, ParseError> {
+    let extractor = extract(sql_query, options)?;
+    Ok(extractor.references.iter().map(|&index| qualified_name_string(&extractor.table_names[index], options)).collect())
+}
+
+fn extract<'a>(sql_query: &str, options: &'a ExtractOptions) -> Result<TableNameExtractor<'a>, ParseError> {
+    extract_traced(sql_query, options, false)
+}
+
+/// Like `extract`, recording the extractor's decisions if `traced` is true.
+fn extract_traced<'a>(sql_query: &str, options: &'a ExtractOptions, traced: bool) -> Result<TableNameExtractor<'a>, ParseError> {
+    if let Some(limit) = options.max_input_bytes {
+        if sql_query.len() > limit {
+            return Err(ParseError::InputTooLarge { size: sql_query.len(), limit });
+        }
+    }
+    match extract_statements(sql_query, options, traced) {
+        Err(err @ ParseError::SyntaxError { .. }) => match reader::strip_statement_parens(sql_query) {
+            // offsets are kept, so an error in the stripped input points past the parentheses
+            Some(stripped) => extract_statements(&stripped, options, traced),
+            None => Err(err),
+        },
+        result => result,
+    }
+}
+
+fn extract_statements<'a>(sql_query: &str, options: &'a ExtractOptions, traced: bool) -> Result<TableNameExtractor<'a>, ParseError> {
+    let mut extractor = TableNameExtractor::new(options);
+    if traced {
+        extractor.trace = Some(Vec::new());
+    }
+    let mut parser = Parser::new(sql_query.as_bytes());
+    loop {
+        match parser.next() {
+            Ok(Some(cmd)) => {
+                if !extractor.extract_table_names_from_cmd(&cmd) && options.fail_on_unsupported {
+                    let (Cmd::Explain(stmt) | Cmd::ExplainQueryPlan(stmt) | Cmd::Stmt(stmt)) = &cmd;
+                    return Err(ParseError::Unsupported { statement: statement_kind(stmt).to_string() });
+                }
+            },
+            Ok(None) => break,
+            Err(err) => return Err(with_dialect_hint(ParseError::from_sql_error(err, &parser), sql_query)),
+        }
+    }
+    Ok(extractor)
+}
+
+/// Appends the `reader::dialect_hint` for the position of a syntax error in
+/// `sql_query`, if any, to the error.
+pub(crate) fn with_dialect_hint(mut err: ParseError, sql_query: &str) -> ParseError {
+    if let ParseError::SyntaxError { message, line, column } = &mut err {
+        if let Some(hint) = reader::dialect_hint(sql_query, *line, *column) {
+            message.push_str(&format!(" ({})", hint));
+        }
+    }
+    err
+}
+
+/// The leading keywords of `stmt`, e.g. `CREATE INDEX`.
+pub fn statement_kind(stmt: &Stmt) -> &'static str {
+    match stmt {
+        Stmt::AlterTable(..) => "ALTER TABLE",
+        Stmt::Analyze(_) => "ANALYZE",
+        Stmt::Attach { .. } => "ATTACH",
+        Stmt::Begin(..) => "BEGIN",
+        Stmt::Commit(_) => "COMMIT",
+        Stmt::CreateIndex { .. } => "CREATE INDEX",
+        Stmt::CreateTable { .. } => "CREATE TABLE",
+        Stmt::CreateTrigger { .. } => "CREATE TRIGGER",
+        Stmt::CreateView { .. } => "CREATE VIEW",
+        Stmt::CreateVirtualTable { .. } => "CREATE VIRTUAL TABLE",
+        Stmt::Delete { .. } => "DELETE",
+        Stmt::Detach(_) => "DETACH",
+        Stmt::DropIndex { .. } => "DROP INDEX",
+        Stmt::DropTable { .. } => "DROP TABLE",
+        Stmt::DropTrigger { .. } => "DROP TRIGGER",
+        Stmt::DropView { .. } => "DROP VIEW",
+        Stmt::Insert { .. } => "INSERT",
+        Stmt::Pragma(..) => "PRAGMA",
+        Stmt::Reindex { .. } => "REINDEX",
+        Stmt::Release(_) => "RELEASE",
+        Stmt::Rollback { .. } => "ROLLBACK",
+        Stmt::Savepoint(_) => "SAVEPOINT",
+        Stmt::Select(_) => "SELECT",
+        Stmt::Update { .. } => "UPDATE",
+        Stmt::Vacuum(..) => "VACUUM",
+    }
+}
+
+/// Returns true if `name` is one of SQLite's aliases for the rowid.
+pub fn is_rowid_alias(name: &str) -> bool {
+    ROWID_ALIASES.iter().any(|alias| alias.eq_ignore_ascii_case(name))
+}
+
+/// Returns true if `identifier` is an SQLite keyword written without quotes.
+/// Such names only parse where the grammar lets the keyword fall back to an
+/// identifier, so they are worth quoting.
+pub fn is_unquoted_keyword(identifier: &str) -> bool {
+    let quoted = identifier.starts_with(['"', '`', '[', '\'']);
+    !quoted && keyword_token(identifier.as_bytes()).is_some()
+}
+
+/// Lints the tables of `sql_query` as written, before any normalization,
+/// returning the sorted names, formatted like `extract_tables`, where the
+/// schema or the table is an unquoted SQLite keyword. The `main` and `temp`
+/// schemas are exempt since they are always written that way.
+pub fn keyword_table_names(sql_query: &str, options: &ExtractOptions) -> Result<Vec<String>, ParseError> {
+    let extractor = extract(sql_query, options)?;
+    let mut keyword_names: Vec<String> = extractor
+        .table_names
+        .iter()
+        .filter(|qn| {
+            let schema = qn.db_name.as_ref().is_some_and(|schema| {
+                !schema.0.eq_ignore_ascii_case("main") && !schema.0.eq_ignore_ascii_case("temp") && is_unquoted_keyword(&schema.0)
+            });
+            schema || is_unquoted_keyword(&qn.name.0)
+        })
+        .map(|qn| qualified_name_string(qn, options))
+        .collect();
+    keyword_names.sort();
+    keyword_names.dedup();
+    Ok(keyword_names)
+}
+
+fn extract_table_name_strings(qualified_names: &[QualifiedName], options: &ExtractOptions) -> HashSet<String> {
+   qualified_names.iter().map(|qn| qualified_name_string(qn, options)).collect()
+}
+
+pub(crate) fn qualified_name_string(qn: &QualifiedName, options: &ExtractOptions) -> String {
+    let part = |name: &Name| name_part(&name_string(name), options);
+    match &qn.db_name {
+        Some(db_name) if !(options.normalize_schema && db_name == "main") => {
+            format!("{}\x1F{}", part(db_name), part(&qn.name))
+        },
+        _ => part(&qn.name),
+    }
+}
+
+/// Applies the quoting, case and escaping options to one part of a name.
+/// Escaping comes last so that lower-casing can not alter the escapes.
+fn name_part(identifier: &str, options: &ExtractOptions) -> String {
+    let mut part = if options.strip_quotes { unquote(identifier) } else { identifier.to_string() };
+    if options.normalize_case {
+        part = part.to_ascii_lowercase();
+    }
+    if options.escape_separator {
+        part = part.replace('\\', "\\\\").replace('\x1F', "\\x1F");
+    }
+    part
+}
+
+/// Splits a name reported with `ExtractOptions::escape_separator` into its
+/// schema and table, undoing the escaping: `a\x1Fb` is `["a", "b"]` and
+/// `a\\x1Fb` is the single table `["a\x1Fb"]`.
+pub fn split_table_name(name: &str) -> Vec<String> {
+    name.split('\x1F')
+        .map(|part| {
+            let mut unescaped = String::with_capacity(part.len());
+            let mut rest = part;
+            while let Some(index) = rest.find('\\') {
+                unescaped.push_str(&rest[..index]);
+                rest = &rest[index + 1..];
+                if let Some(after) = rest.strip_prefix("x1F") {
+                    unescaped.push('\x1F');
+                    rest = after;
+                } else if let Some(after) = rest.strip_prefix('\\') {
+                    unescaped.push('\\');
+                    rest = after;
+                } else {
+                    unescaped.push('\\');
+                }
+            }
+            unescaped.push_str(rest);
+            unescaped
+        })
+        .collect()
+}
+
+/// The parser drops the original spelling of keywords used as identifiers
+/// (e.g. the `temp` schema) and hands them back upper-cased, so those are
+/// lower-cased to match how they are normally written.
+fn name_string(name: &Name) -> String {
+    identifier_string(&name.0)
+}
+
+/// Removes the quotes of a quoted identifier, undoubling escaped quotes.
+fn unquote(identifier: &str) -> String {
+    let mut chars = identifier.chars();
+    let (Some(open), Some(close)) = (chars.next(), chars.next_back()) else {
+        return identifier.to_string();
+    };
+    match (open, close) {
+        ('"', '"') | ('`', '`') | ('\'', '\'') => {
+            let doubled = format!("{}{}", open, open);
+            chars.as_str().replace(&doubled, &open.to_string())
+        },
+        ('[', ']') => chars.as_str().to_string(),
+        _ => identifier.to_string(),
+    }
+}
+
+fn identifier_string(identifier: &str) -> String {
+    if is_unquoted_keyword(identifier) {
+        identifier.to_ascii_lowercase()
+    } else {
+        identifier.to_string()
+    }
+}
+
+/// The sources read by the query of one common table expression.
+struct CteSources {
+    statement: usize,
+    name: Name,
+    tables: Vec<QualifiedName>,
+    /// Indexes into `cte_sources` of the CTEs, possibly this one, read by the query.
+    ctes: Vec<usize>,
+}
+
+struct TableNameExtractor<'a> {
+    options: &'a ExtractOptions,
+    table_names: Vec<QualifiedName>,
+    /// The index into `table_names` of every table reference, in visiting order.
+    references: Vec<usize>,
+    /// Indexes into `table_names`, so reads and writes are spelled like the
+    /// tables reported by `extract_tables`.
+    read_tables: Vec<usize>,
+    written_tables: Vec<usize>,
+    aliases: HashMap<String, String>,
+    /// Aliases of derived tables and result columns, which stand for no table.
+    other_aliases: HashSet<String>,
+    columns: HashSet<String>,
+    /// The CTEs in scope with their index into `cte_sources`, innermost last.
+    cte_scopes: Vec<Vec<(Name, usize)>>,
+    cte_sources: Vec<CteSources>,
+    /// Indexes into `cte_sources` of the CTE queries being visited.
+    open_ctes: Vec<usize>,
+    /// The decisions taken, recorded only when explaining an extraction.
+    trace: Option<Vec<ExtractionStep>>,
+    /// The clauses enclosing the node being visited, outermost first.
+    clauses: Vec<&'static str>,
+    statement: usize,
+    /// Bound parameters and subqueries seen so far.
+    runtime_values: usize,
+    /// Whether a table function is called with a parameter or subquery argument.
+    dynamic_source: bool,
+    /// The joins of each statement.
+    joins: Vec<usize>,
+}
+
+impl<'a> TableNameExtractor<'a> {
+    fn new(options: &'a ExtractOptions) -> Self {
+        TableNameExtractor {
+            options,
+            table_names: Vec::new(),
+            references: Vec::new(),
+            read_tables: Vec::new(),
+            written_tables: Vec::new(),
+            aliases: HashMap::new(),
+            other_aliases: HashSet::new(),
+            columns: HashSet::new(),
+            cte_scopes: Vec::new(),
+            cte_sources: Vec::new(),
+            open_ctes: Vec::new(),
+            trace: None,
+            clauses: Vec::new(),
+            statement: 0,
+            runtime_values: 0,
+            dynamic_source: false,
+            joins: Vec::new(),
+        }
+    }
+
+    fn in_clause(&mut self, clause: &'static str, visit: impl FnOnce(&mut Self)) {
+        self.clauses.push(clause);
+        visit(self);
+        self.clauses.pop();
+    }
+
+    fn record(&mut self, decision: impl FnOnce(&Self) -> String) {
+        if self.trace.is_some() {
+            let step = ExtractionStep { statement: self.statement, clause: self.clauses.join(" > "), decision: decision(self) };
+            self.trace.get_or_insert_with(Vec::new).push(step);
+        }
+    }
+
+    fn display_name(&self, qualified_name: &QualifiedName) -> String {
+        qualified_name_string(qualified_name, self.options).replace('\x1F', ".")
+    }
+
+    /// Returns false if the statement is of a kind the extractor does not
+    /// understand, in which case it contributes no tables.
+    fn extract_table_names_from_cmd(&mut self, cmd: &Cmd) -> bool {
+        match cmd {
+            Cmd::Explain(stmt) | Cmd::ExplainQueryPlan(stmt) | Cmd::Stmt(stmt) => {
+                self.statement += 1;
+                self.joins.push(0);
+                let mut supported = true;
+                self.in_clause(statement_kind(stmt), |this| supported = this.extract_table_names_from_stmt(stmt));
+                if !supported {
+                    self.record(|_| format!("skipped unsupported {} statement", statement_kind(stmt)));
+                }
+                supported
+            },
+        }
+    }
+
+    fn extract_table_names_from_stmt(&mut self, stmt: &Stmt) -> bool {
+        match stmt {
+            Stmt::Select(select) => {
+                self.extract_table_names_from_select(select);
+            },
+            Stmt::Insert { with, tbl_name, columns, body, returning, .. } => {
+                let scoped = self.push_cte_scope(with);
+                self.add_written_table(tbl_name);
+                self.add_column_names(columns.as_ref());
+                if let InsertBody::Select(select, upsert) = body {
+                    self.extract_table_names_from_select(select);
+                    if let Some(upsert) = upsert {
+                        self.in_clause("ON CONFLICT", |this| this.extract_table_names_from_upsert(upsert));
+                    }
+                }
+                self.in_clause("RETURNING", |this| this.extract_table_names_from_result_columns(returning.as_deref()));
+                self.pop_cte_scope(scoped);
+            },
+            Stmt::Update { with, tbl_name, sets, from, where_clause, returning, order_by, limit, .. } => {
+                let scoped = self.push_cte_scope(with);
+                self.add_written_table(tbl_name);
+                self.in_clause("SET", |this| this.extract_table_names_from_sets(sets));
+                if let Some(from_clause) = from {
+                    self.in_clause("FROM", |this| this.extract_table_names_from_from_clause(from_clause));
+                }
+                if let Some(where_clause) = where_clause {
+                    self.in_clause("WHERE", |this| this.extract_table_names_from_expr(where_clause));
+                }
+                self.in_clause("RETURNING", |this| this.extract_table_names_from_result_columns(returning.as_deref()));
+                self.in_clause("ORDER BY", |this| this.extract_table_names_from_sorted_columns(order_by.as_deref()));
+                self.in_clause("LIMIT", |this| this.extract_table_names_from_limit(limit));
+                self.pop_cte_scope(scoped);
+            },
+            Stmt::Delete { with, tbl_name, where_clause, returning, order_by, limit, .. } => {
+                let scoped = self.push_cte_scope(with);
+                self.add_written_table(tbl_name);
+                if let Some(where_clause) = where_clause {
+                    self.in_clause("WHERE", |this| this.extract_table_names_from_expr(where_clause));
+                }
+                self.in_clause("RETURNING", |this| this.extract_table_names_from_result_columns(returning.as_deref()));
+                self.in_clause("ORDER BY", |this| this.extract_table_names_from_sorted_columns(order_by.as_deref()));
+                self.in_clause("LIMIT", |this| this.extract_table_names_from_limit(limit));
+                self.pop_cte_scope(scoped);
+            },
+            Stmt::CreateTable { temporary, tbl_name, body, .. } => {
+                let tbl_name = self.created_name(tbl_name, *temporary);
+                self.add_written_table(&tbl_name);
+                self.extract_table_names_from_create_table_body(&tbl_name, body);
+            },
+            Stmt::CreateView { temporary, view_name, select, .. } => {
+                self.add_written_table(&self.created_name(view_name, *temporary));
+                self.in_clause("AS", |this| this.extract_table_names_from_select(select));
+            },
+            Stmt::CreateTrigger { tbl_name, when_clause, commands, .. } => {
+                self.in_clause("ON", |this| this.add_read_table(tbl_name));
+                if let Some(when_clause) = when_clause {
+                    self.in_clause("WHEN", |this| this.extract_table_names_from_expr(when_clause));
+                }
+                self.in_clause("BEGIN", |this| {
+                    for command in commands {
+                        this.extract_table_names_from_trigger_cmd(command);
+                    }
+                });
+            },
+            Stmt::Begin(..) | Stmt::Commit(_) | Stmt::Rollback { .. } | Stmt::Savepoint(_)
+            | Stmt::Release(_) | Stmt::Vacuum(..) => {},
+            _ => return false,
+        }
+        true
+    }
+
+    fn extract_table_names_from_create_table_body(&mut self, tbl_name: &QualifiedName, body: &CreateTableBody) {
+        match body {
+            CreateTableBody::ColumnsAndConstraints { columns, constraints, .. } => {
+                for column in columns.values() {
+                    for constraint in &column.constraints {
+                        match &constraint.constraint {
+                            ColumnConstraint::ForeignKey { clause, .. } => {
+                                self.add_parent_table(tbl_name, &clause.tbl_name);
+                            },
+                            ColumnConstraint::Check(expr) => {
+                                self.in_clause("CHECK", |this| this.extract_table_names_from_expr(expr));
+                            },
+                            ColumnConstraint::Default(expr) => {
+                                self.in_clause("DEFAULT", |this| this.extract_table_names_from_expr(expr));
+                            },
+                            ColumnConstraint::Generated { expr, .. } => {
+                                self.in_clause("GENERATED", |this| this.extract_table_names_from_expr(expr));
+                            },
+                            _ => {},
+                        }
+                    }
+                }
+                for constraint in constraints.as_deref().unwrap_or_default() {
+                    match &constraint.constraint {
+                        TableConstraint::ForeignKey { clause, .. } => {
+                            self.add_parent_table(tbl_name, &clause.tbl_name);
+                        },
+                        TableConstraint::Check(expr) => {
+                            self.in_clause("CHECK", |this| this.extract_table_names_from_expr(expr));
+                        },
+                        _ => {},
+                    }
+                }
+            },
+            CreateTableBody::AsSelect(select) => {
+                self.in_clause("AS", |this| this.extract_table_names_from_select(select));
+            },
+        }
+    }
+
+    fn created_name(&self, name: &QualifiedName, temporary: bool) -> QualifiedName {
+        match name.db_name {
+            None if temporary && self.options.qualify_temp => {
+                QualifiedName { db_name: Some(Name("temp".to_string())), name: name.name.clone(), alias: None }
+            },
+            _ => name.clone(),
+        }
+    }
+
+    fn extract_table_names_from_trigger_cmd(&mut self, command: &TriggerCmd) {
+        let table = |name: &Name| QualifiedName { db_name: None, name: name.clone(), alias: None };
+        match command {
+            TriggerCmd::Update { tbl_name, sets, from, where_clause, .. } => {
+                self.in_clause("UPDATE", |this| {
+                    this.add_written_table(&table(tbl_name));
+                    this.in_clause("SET", |this| this.extract_table_names_from_sets(sets));
+                    if let Some(from_clause) = from {
+                        this.in_clause("FROM", |this| this.extract_table_names_from_from_clause(from_clause));
+                    }
+                    if let Some(where_clause) = where_clause {
+                        this.in_clause("WHERE", |this| this.extract_table_names_from_expr(where_clause));
+                    }
+                });
+            },
+            TriggerCmd::Insert { tbl_name, col_names, select, upsert, returning, .. } => {
+                self.in_clause("INSERT", |this| {
+                    this.add_written_table(&table(tbl_name));
+                    this.add_column_names(col_names.as_ref());
+                    this.extract_table_names_from_select(select);
+                    if let Some(upsert) = upsert {
+                        this.in_clause("ON CONFLICT", |this| this.extract_table_names_from_upsert(upsert));
+                    }
+                    this.in_clause("RETURNING", |this| this.extract_table_names_from_result_columns(returning.as_deref()));
+                });
+            },
+            TriggerCmd::Delete { tbl_name, where_clause } => {
+                self.in_clause("DELETE", |this| {
+                    this.add_written_table(&table(tbl_name));
+                    if let Some(where_clause) = where_clause {
+                        this.in_clause("WHERE", |this| this.extract_table_names_from_expr(where_clause));
+                    }
+                });
+            },
+            TriggerCmd::Select(select) => {
+                self.in_clause("SELECT", |this| this.extract_table_names_from_select(select));
+            },
+        }
+    }
+
+    fn add_parent_table(&mut self, tbl_name: &QualifiedName, parent: &Name) {
+        // a foreign key can only reference a table in the same schema
+        let parent = QualifiedName { db_name: tbl_name.db_name.clone(), name: parent.clone(), alias: None };
+        self.in_clause("FOREIGN KEY", |this| this.add_read_table(&parent));
+    }
+
+    fn extract_table_names_from_upsert(&mut self, upsert: &Upsert) {
+        if let Some(index) = &upsert.index {
+            self.extract_table_names_from_sorted_columns(Some(&index.targets));
+            if let Some(where_clause) = &index.where_clause {
+                self.extract_table_names_from_expr(where_clause);
+            }
+        }
+        if let UpsertDo::Set { sets, where_clause } = &upsert.do_clause {
+            self.extract_table_names_from_sets(sets);
+            if let Some(where_clause) = where_clause {
+                self.extract_table_names_from_expr(where_clause);
+            }
+        }
+        if let Some(next) = &upsert.next {
+            self.extract_table_names_from_upsert(next);
+        }
+    }
+
+    fn extract_table_names_from_limit(&mut self, limit: &Option<Limit>) {
+        if let Some(limit) = limit {
+            self.extract_table_names_from_expr(&limit.expr);
+            if let Some(offset) = &limit.offset {
+                self.extract_table_names_from_expr(offset);
+            }
+        }
+    }
+
+    fn extract_table_names_from_sets(&mut self, sets: &[Set]) {
+        for set in sets {
+            self.add_column_names(Some(&set.col_names));
+            self.extract_table_names_from_expr(&set.expr);
+        }
+    }
+
+    fn extract_table_names_from_result_columns(&mut self, columns: Option<&[ResultColumn]>) {
+        for column in columns.unwrap_or_default() {
+            if let ResultColumn::Expr(expr, alias) = column {
+                self.extract_table_names_from_expr(expr);
+                self.add_other_alias(alias);
+            }
+        }
+    }
+
+    fn extract_table_names_from_select(&mut self, select: &Select) {
+        let scoped = self.push_cte_scope(&select.with);
+        self.extract_table_names_from_select_body(&select.body);
+        self.in_clause("ORDER BY", |this| this.extract_table_names_from_sorted_columns(select.order_by.as_deref()));
+        self.in_clause("LIMIT", |this| this.extract_table_names_from_limit(&select.limit));
+        self.pop_cte_scope(scoped);
+    }
+
+    /// Brings the CTEs of `with` into scope and extracts the tables their
+    /// queries read. The names are visible inside every CTE query so that
+    /// recursive references are not reported as tables.
+    fn push_cte_scope(&mut self, with: &Option<With>) -> bool {
+        let Some(with) = with else {
+            return false;
+        };
+        let first = self.cte_sources.len();
+        self.cte_scopes.push(with.ctes.iter().enumerate().map(|(index, cte)| (cte.tbl_name.clone(), first + index)).collect());
+        for cte in &with.ctes {
+            let statement = self.statement;
+            self.cte_sources.push(CteSources { statement, name: cte.tbl_name.clone(), tables: Vec::new(), ctes: Vec::new() });
+        }
+        self.in_clause("WITH", |this| {
+            for (index, cte) in with.ctes.iter().enumerate() {
+                this.record(|_| format!("defined CTE {}", name_string(&cte.tbl_name)));
+                this.open_ctes.push(first + index);
+                this.extract_table_names_from_select(&cte.select);
+                this.open_ctes.pop();
+            }
+        });
+        true
+    }
+
+    fn pop_cte_scope(&mut self, scoped: bool) {
+        if scoped {
+            self.cte_scopes.pop();
+        }
+    }
+
+    fn is_cte(&self, qualified_name: &QualifiedName) -> bool {
+        qualified_name.db_name.is_none() && self.cte_index(&qualified_name.name).is_some()
+    }
+
+    /// The index into `cte_sources` of the innermost CTE called `name`.
+    fn cte_index(&self, name: &Name) -> Option<usize> {
+        self.cte_scopes.iter().rev().flatten().find(|(cte, _)| cte == name).map(|(_, index)| *index)
+    }
+
+    fn extract_table_names_from_select_body(&mut self, body: &SelectBody) {
+        self.extract_table_names_from_one_select(&body.select);
+        if let Some(compounds) = &body.compounds {
+            for compound in compounds {
+                self.extract_table_names_from_one_select(&compound.select);
+            }
+        }
+    }
+
+    fn extract_table_names_from_one_select(&mut self, one_select: &OneSelect) {
+        match one_select {
+            OneSelect::Select { columns, from, where_clause, group_by, window_clause, .. } => {
+                self.extract_table_names_from_result_columns(Some(columns));
+                if let Some(from_clause) = from {
+                    self.in_clause("FROM", |this| this.extract_table_names_from_from_clause(from_clause));
+                }
+                if let Some(where_clause) = where_clause {
+                    self.in_clause("WHERE", |this| this.extract_table_names_from_expr(where_clause));
+                }
+                if let Some(group_by) = group_by {
+                    self.in_clause("GROUP BY", |this| this.extract_table_names_from_exprs(Some(&group_by.exprs)));
+                    if let Some(having) = &group_by.having {
+                        self.in_clause("HAVING", |this| this.extract_table_names_from_expr(having));
+                    }
+                }
+                for window_def in window_clause.as_deref().unwrap_or_default() {
+                    self.in_clause("WINDOW", |this| this.extract_table_names_from_window(&window_def.window));
+                }
+            },
+            OneSelect::Values(rows) => {
+                self.in_clause("VALUES", |this| {
+                    for row in rows {
+                        this.extract_table_names_from_exprs(Some(row));
+                    }
+                });
+            },
+        }
+    }
+
+    fn extract_table_names_from_from_clause(&mut self, from_clause: &FromClause) {
+        if let Some(select_table) = &from_clause.select {
+            self.extract_table_names_from_select_table(select_table);
+        }
+        if let Some(joins) = &from_clause.joins {
+            if let Some(statement_joins) = self.joins.last_mut() {
+                *statement_joins += joins.len();
+            }
+            for join in joins {
+                self.extract_table_names_from_select_table(&join.table);
+                match &join.constraint {
+                    Some(JoinConstraint::On(expr)) => self.in_clause("ON", |this| this.extract_table_names_from_expr(expr)),
+                    Some(JoinConstraint::Using(columns)) => self.add_column_names(Some(columns)),
+                    None => {},
+                }
+            }
+        }
+    }
+
+    fn extract_table_names_from_select_table(&mut self, select_table: &SelectTable) {
+        match select_table {
+            SelectTable::Table(qualified_name, _, _) if self.is_cte(qualified_name) => {
+                self.record(|_| format!("skipped {}: CTE", name_string(&qualified_name.name)));
+                self.add_cte_reference(&qualified_name.name);
+            },
+            SelectTable::Table(qualified_name, alias, _) => {
+                self.add_read_table(qualified_name);
+                self.add_alias(alias, qualified_name);
+            },
+            SelectTable::TableCall(qualified_name, args, alias) => {
+                if self.options.include_functions || !self.options.is_table_function(&qualified_name.name) {
+                    self.add_read_table(qualified_name);
+                    self.add_alias(alias, qualified_name);
+                } else {
+                    self.record(|this| format!("skipped {}: table function", this.display_name(qualified_name)));
+                }
+                self.extract_table_names_from_function_args(args.as_deref());
+            },
+            SelectTable::Select(select, alias) => {
+                self.in_clause("derived table", |this| this.extract_table_names_from_select(select));
+                self.add_other_alias(alias);
+            },
+            SelectTable::Sub(from_clause, alias) => {
+                self.extract_table_names_from_from_clause(from_clause);
+                self.add_other_alias(alias);
+            },
+        }
+    }
+
+    fn extract_table_names_from_exprs(&mut self, exprs: Option<&[Expr]>) {
+        for expr in exprs.unwrap_or_default() {
+            self.extract_table_names_from_expr(expr);
+        }
+    }
+
+    /// Any call in table position is table-valued, whether or not it is one of
+    /// `ExtractOptions::table_functions`.
+    fn extract_table_names_from_function_args(&mut self, args: Option<&[Expr]>) {
+        let runtime_values = self.runtime_values;
+        self.extract_table_names_from_exprs(args);
+        if self.runtime_values > runtime_values {
+            self.dynamic_source = true;
+        }
+    }
+
+    fn extract_table_names_from_sorted_columns(&mut self, columns: Option<&[SortedColumn]>) {
+        for column in columns.unwrap_or_default() {
+            self.extract_table_names_from_expr(&column.expr);
+        }
+    }
+
+    fn extract_table_names_from_function_tail(&mut self, filter_over: &Option<FunctionTail>) {
+        if let Some(tail) = filter_over {
+            if let Some(filter) = &tail.filter_clause {
+                self.extract_table_names_from_expr(filter);
+            }
+            if let Some(over) = &tail.over_clause {
+                if let Over::Window(window) = over.as_ref() {
+                    self.extract_table_names_from_window(window);
+                }
+            }
+        }
+    }
+
+    fn extract_table_names_from_window(&mut self, window: &Window) {
+        self.extract_table_names_from_exprs(window.partition_by.as_deref());
+        self.extract_table_names_from_sorted_columns(window.order_by.as_deref());
+    }
+
+    fn extract_table_names_from_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Between { lhs, start, end, .. } => {
+                self.extract_table_names_from_expr(lhs);
+                self.extract_table_names_from_expr(start);
+                self.extract_table_names_from_expr(end);
+            },
+            Expr::Binary(lhs, _, rhs) => {
+                self.extract_table_names_from_expr(lhs);
+                self.extract_table_names_from_expr(rhs);
+            },
+            Expr::Case { base, when_then_pairs, else_expr } => {
+                if let Some(base) = base {
+                    self.extract_table_names_from_expr(base);
+                }
+                for (when, then) in when_then_pairs {
+                    self.extract_table_names_from_expr(when);
+                    self.extract_table_names_from_expr(then);
+                }
+                if let Some(else_expr) = else_expr {
+                    self.extract_table_names_from_expr(else_expr);
+                }
+            },
+            Expr::Cast { expr, .. } | Expr::Collate(expr, _) | Expr::IsNull(expr)
+            | Expr::NotNull(expr) | Expr::Unary(_, expr) => {
+                self.extract_table_names_from_expr(expr);
+            },
+            Expr::Exists(select) => {
+                self.runtime_values += 1;
+                self.in_clause("EXISTS", |this| this.extract_table_names_from_select(select));
+            },
+            Expr::Subquery(select) => {
+                self.runtime_values += 1;
+                self.in_clause("subquery", |this| this.extract_table_names_from_select(select));
+            },
+            Expr::FunctionCall { args, order_by, filter_over, .. } => {
+                self.extract_table_names_from_exprs(args.as_deref());
+                self.extract_table_names_from_sorted_columns(order_by.as_deref());
+                self.extract_table_names_from_function_tail(filter_over);
+            },
+            Expr::FunctionCallStar { filter_over, .. } => {
+                self.extract_table_names_from_function_tail(filter_over);
+            },
+            Expr::InList { lhs, rhs, .. } => {
+                self.extract_table_names_from_expr(lhs);
+                self.extract_table_names_from_exprs(rhs.as_deref());
+            },
+            Expr::InSelect { lhs, rhs, .. } => {
+                self.extract_table_names_from_expr(lhs);
+                self.runtime_values += 1;
+                self.in_clause("IN", |this| this.extract_table_names_from_select(rhs));
+            },
+            Expr::InTable { lhs, rhs, args, .. } => {
+                self.extract_table_names_from_expr(lhs);
+                let skipped = match args {
+                    None if self.is_cte(rhs) => Some("CTE"),
+                    Some(_) if !self.options.include_functions && self.options.is_table_function(&rhs.name) => {
+                        Some("table function")
+                    },
+                    _ => None,
+                };
+                match skipped {
+                    Some(reason) => {
+                        self.record(|this| format!("skipped {}: {}", this.display_name(rhs), reason));
+                        if args.is_none() {
+                            self.add_cte_reference(&rhs.name);
+                        }
+                    },
+                    None => self.in_clause("IN", |this| this.add_read_table(rhs)),
+                }
+                if args.is_some() {
+                    self.extract_table_names_from_function_args(args.as_deref());
+                }
+            },
+            Expr::Like { lhs, rhs, escape, .. } => {
+                self.extract_table_names_from_expr(lhs);
+                self.extract_table_names_from_expr(rhs);
+                if let Some(escape) = escape {
+                    self.extract_table_names_from_expr(escape);
+                }
+            },
+            Expr::Parenthesized(exprs) => {
+                self.extract_table_names_from_exprs(Some(exprs));
+            },
+            Expr::Raise(_, message) => {
+                if let Some(message) = message {
+                    self.extract_table_names_from_expr(message);
+                }
+            },
+            Expr::Id(column) => {
+                self.add_column(&[], &column.0);
+            },
+            Expr::Qualified(table, column) => {
+                self.add_column(&[table], &column.0);
+            },
+            Expr::DoublyQualified(schema, table, column) => {
+                self.add_column(&[schema, table], &column.0);
+            },
+            Expr::Variable(_) => {
+                self.runtime_values += 1;
+            },
+            Expr::Literal(_) | Expr::Name(_) => {},
+        }
+    }
+
+    fn add_alias(&mut self, alias: &Option<As>, qualified_name: &QualifiedName) {
+        if let Some(As::As(alias) | As::Elided(alias)) = alias {
+            self.aliases.insert(name_part(&name_string(alias), self.options), qualified_name_string(qualified_name, self.options));
+        }
+    }
+
+    fn add_other_alias(&mut self, alias: &Option<As>) {
+        if let Some(As::As(alias) | As::Elided(alias)) = alias {
+            self.other_aliases.insert(name_part(&name_string(alias), self.options));
+        }
+    }
+
+    fn add_column(&mut self, qualifiers: &[&Name], column: &str) {
+        if is_rowid_alias(column) {
+            return;
+        }
+        let mut parts: Vec<String> = qualifiers.iter().map(|qualifier| name_part(&name_string(qualifier), self.options)).collect();
+        parts.push(name_part(&identifier_string(column), self.options));
+        self.columns.insert(parts.join("\x1F"));
+    }
+
+    /// Adds the unqualified columns named by an INSERT column list, a SET
+    /// assignment or a USING join constraint.
+    fn add_column_names(&mut self, columns: Option<&DistinctNames>) {
+        for column in columns.into_iter().flat_map(|columns| columns.iter()) {
+            self.add_column(&[], &column.0);
+        }
+    }
+
+    fn add_cte_reference(&mut self, cte: &Name) {
+        let Some(cte) = self.cte_index(cte) else {
+            return;
+        };
+        for &index in &self.open_ctes {
+            self.cte_sources[index].ctes.push(cte);
+        }
+    }
+
+    fn add_read_table(&mut self, name: &QualifiedName) {
+        self.record(|this| format!("added {}", this.display_name(name)));
+        for &index in &self.open_ctes {
+            let tables = &mut self.cte_sources[index].tables;
+            if !tables.contains(name) {
+                tables.push(name.clone());
+            }
+        }
+        let index = self.add_unique_qualified_name(name);
+        if !self.read_tables.contains(&index) {
+            self.read_tables.push(index);
+        }
+    }
+
+    fn add_written_table(&mut self, name: &QualifiedName) {
+        self.record(|this| format!("added {} (written)", this.display_name(name)));
+        let index = self.add_unique_qualified_name(name);
+        if !self.written_tables.contains(&index) {
+            self.written_tables.push(index);
+        }
+    }
+
+    /// Records a reference to `new_name`, returning its index in `table_names`.
+    fn add_unique_qualified_name(&mut self, new_name: &QualifiedName) -> usize {
+        let index = match self.table_names.iter().position(|name| name == new_name) {
+            Some(index) => index,
+            None => {
+                self.table_names.push(new_name.clone());
+                self.table_names.len() - 1
+            },
+        };
+        self.references.push(index);
+        index
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simple_sql() {
+        let sql_query = "SELECT *\nFROM bananas\nWHERE color = 'red'";
+        let mut expected = HashSet::new();
+        expected.insert("bananas".to_string());
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+    }
+
+    #[test]
+    fn test_sql_join() {
+        let sql_query = "Select m.title, r.id\n FROM Movies m\n INNER JOIN (\nSELECT rs.movie_id\n FROM Rooms r2 \n WHERE r2.seaats >= 50 \n ) AS r \n ON m.id = r.movide_id AND m.title != 'Batman';";
+        let mut expected = HashSet::new();
+        expected.insert("Movies".to_string());
+        expected.insert("Rooms".to_string());
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+    }
+
+    #[test]
+    fn test_sql_join_count() {
+        let options = ExtractOptions::default();
+        let sql_query = "Select m.title FROM Movies m INNER JOIN (SELECT movie_id FROM Rooms) AS r ON m.id = r.movie_id";
+        assert_eq!(join_count(sql_query, &options), Ok(vec![1]));
+        assert_eq!(join_count("SELECT * FROM a", &options), Ok(vec![0]));
+        let sql_query = "SELECT * FROM a, b WHERE x IN (SELECT x FROM c JOIN d LEFT JOIN (SELECT * FROM e, f) g)";
+        assert_eq!(join_count(sql_query, &options), Ok(vec![4]));
+        assert_eq!(join_count("UPDATE t SET x = 1 FROM a JOIN b; PRAGMA optimize; SELECT * FROM c JOIN d, e", &options), Ok(vec![1, 0, 2]));
+    }
+
+    #[test]
+    fn test_sql_parenthesized_join_group() {
+        let sql_query = "SELECT * FROM t1 LEFT JOIN (t2 JOIN t3 ON t2.id = (SELECT id FROM s1)) ON t1.id IN (SELECT id FROM s2)";
+        let mut expected = HashSet::new();
+        for table in ["t1", "t2", "t3", "s1", "s2"] {
+            expected.insert(table.to_string());
+        }
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+    }
+
+    #[test]
+    fn test_sql_union() {
+        let sql_query = "SELECT *\nFROM a\nUNION\nSELECT *\nFROM b";
+        let mut expected = HashSet::new();
+        expected.insert("a".to_string());
+        expected.insert("b".to_string());
+	assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+    }
+
+    #[test]
+    fn test_sql_sub_query() {
+        let sql_query = "SELECT a.color\nFROM (\nSELECT b.color\nFROM bananas b\n) z JOIN apples a\nON a.color = b.color";
+	let mut expected = HashSet::new();
+        expected.insert("apples".to_string());
+        expected.insert("bananas".to_string());
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+    }
+
+    #[test]
+    fn test_sql_backticks() {
+        let sql_query = "SELECT\n  *\nFROM\n  `hats` h\nWHERE\n  h.color == 'red'\nGROUP BY\n  h.color, h.material\nHAVING\n  COUNT(h.quantity) >= 200\nORDER BY\n  h.color DESC\nLIMIT\n  20\nOFFSET\n  10";
+        let mut expected = HashSet::new();
+        expected.insert("`hats`".to_string());
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+    }
+
+    #[test]
+    fn test_sql_mixed_quoting_styles() {
+        let sql_query = "SELECT *\nFROM `a`\n  JOIN \"b\" ON `a`.id = \"b\".a_id\n  JOIN [c] ON [c].b_id = \"b\".id\n  JOIN \"ext\".\"Say \"\"hi\"\"\" ON 1";
+        let mut expected = HashSet::new();
+        expected.insert("`a`".to_string());
+        expected.insert("\"b\"".to_string());
+        expected.insert("[c]".to_string());
+        expected.insert("\"ext\"\x1F\"Say \"\"hi\"\"\"".to_string());
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+        let options = ExtractOptions { strip_quotes: true, normalize_case: true, ..ExtractOptions::default() };
+        let mut expected = HashSet::new();
+        expected.insert("a".to_string());
+        expected.insert("b".to_string());
+        expected.insert("c".to_string());
+        expected.insert("ext\x1Fsay \"hi\"".to_string());
+        assert_eq!(extract_tables(sql_query, &options).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_sql_derived_table_alias_matching_table() {
+        let sql_query = "SELECT *\nFROM (\nSELECT *\nFROM real\n) real";
+        let mut expected = HashSet::new();
+        expected.insert("real".to_string());
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+        let options = ExtractOptions::default();
+        let extractor = extract(sql_query, &options).unwrap();
+        assert_eq!(extractor.table_names.len(), 1);
+        assert!(extractor.aliases.is_empty());
+    }
+
+    #[test]
+    fn test_sql_values_derived_table() {
+        let sql_query = "SELECT * FROM (VALUES (1), (2)) AS v";
+        let options = ExtractOptions::default();
+        assert_eq!(extract_tables(sql_query, &options).unwrap(), HashSet::new());
+        assert!(extract_aliases(sql_query, &options).unwrap().is_empty());
+        // SQLite has no derived column lists, so `v(x)` is rejected rather than guessed at
+        let sql_query = "SELECT * FROM (VALUES (1), (2)) AS v(x)";
+        assert!(matches!(extract_tables(sql_query, &options), Err(ParseError::SyntaxError { line: 1, column: 38, .. })));
+    }
+
+    #[test]
+    fn test_sql_db_name() {
+        let sql_query = "SELECT *\nFROM apples.bananas\nWHERE color = 'red'";
+        let mut expected = HashSet::new();
+        expected.insert(format!("{}\x1F{}", "apples", "bananas").to_string());
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+    }
+
+    #[test]
+    fn test_sql_same_table_different_schemas() {
+        let sql_query = "SELECT *\nFROM main.t\nJOIN temp.t ON main.t.id = temp.t.id";
+        let mut expected = HashSet::new();
+        expected.insert(format!("{}\x1F{}", "main", "t").to_string());
+        expected.insert(format!("{}\x1F{}", "temp", "t").to_string());
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+    }
+
+    #[test]
+    fn test_sql_insert_multi_row_values() {
+        let sql_query = "INSERT INTO t VALUES ((SELECT a FROM x)), ((SELECT b FROM y))";
+        let mut expected = HashSet::new();
+        expected.insert("t".to_string());
+        expected.insert("x".to_string());
+        expected.insert("y".to_string());
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+    }
+
+    #[test]
+    fn test_sql_insert_select_upsert() {
+        let sql_query = "INSERT INTO t (id, total)\nSELECT id, total FROM staging WHERE 1\nON CONFLICT (id) DO UPDATE SET total = (SELECT max(total) FROM archive)\nRETURNING (SELECT count(*) FROM audit)";
+        let mut expected = HashSet::new();
+        expected.insert("t".to_string());
+        expected.insert("staging".to_string());
+        expected.insert("archive".to_string());
+        expected.insert("audit".to_string());
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+    }
+
+    #[test]
+    fn test_sql_where_function_argument_subquery() {
+        let sql_query = "SELECT * FROM t WHERE length((SELECT name FROM names WHERE id = t.id)) > 3";
+        let mut expected = HashSet::new();
+        expected.insert("t".to_string());
+        expected.insert("names".to_string());
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+    }
+
+    #[test]
+    fn test_sql_select_core_subqueries() {
+        let sql_query = "SELECT (SELECT max(id) FROM a), count(*) FILTER (WHERE x IN (SELECT x FROM b))\nFROM t\nWHERE EXISTS (SELECT 1 FROM c)\nGROUP BY (SELECT g FROM d)\nHAVING sum(x) > (SELECT min(x) FROM e)\nWINDOW w AS (PARTITION BY (SELECT p FROM f))";
+        let mut expected = HashSet::new();
+        for name in ["t", "a", "b", "c", "d", "e", "f"] {
+            expected.insert(name.to_string());
+        }
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+    }
+
+    #[test]
+    fn test_sql_order_by_and_limit_subqueries() {
+        let sql_query = "SELECT * FROM t ORDER BY (SELECT rank FROM r WHERE r.id = t.id) LIMIT (SELECT o FROM cfg), 10";
+        let mut expected = HashSet::new();
+        expected.insert("t".to_string());
+        expected.insert("r".to_string());
+        expected.insert("cfg".to_string());
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+        let sql_query = "SELECT * FROM t LIMIT (SELECT n FROM a) OFFSET (SELECT o FROM b)";
+        let mut expected = HashSet::new();
+        expected.insert("t".to_string());
+        expected.insert("a".to_string());
+        expected.insert("b".to_string());
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+    }
+
+    #[test]
+    fn test_sql_like_escape() {
+        let sql_query = "SELECT * FROM t WHERE col LIKE (SELECT p FROM patterns) ESCAPE '\\'";
+        let mut expected = HashSet::new();
+        expected.insert("t".to_string());
+        expected.insert("patterns".to_string());
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+
+        let sql_query = "SELECT * FROM t WHERE col NOT LIKE 'a%' ESCAPE (SELECT e FROM escapes)";
+        let mut expected = HashSet::new();
+        expected.insert("t".to_string());
+        expected.insert("escapes".to_string());
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+    }
+
+    #[test]
+    fn test_sql_rowid_pseudo_columns() {
+        let sql_query = "SELECT rowid FROM t";
+        let mut expected = HashSet::new();
+        expected.insert("t".to_string());
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+        assert_eq!(extract_columns(sql_query, &ExtractOptions::default()).unwrap(), HashSet::new());
+    }
+
+    #[test]
+    fn test_sql_columns() {
+        let sql_query = "SELECT t.oid, _ROWID_, name, t.price, main.t.qty FROM t WHERE t.rowid > 10";
+        let mut expected = HashSet::new();
+        expected.insert("name".to_string());
+        expected.insert(format!("{}\x1F{}", "t", "price"));
+        expected.insert(format!("{}\x1F{}\x1F{}", "main", "t", "qty"));
+        assert_eq!(extract_columns(sql_query, &ExtractOptions::default()).unwrap(), expected);
+        let options = ExtractOptions { normalize_case: true, strip_quotes: true, ..ExtractOptions::default() };
+        let mut expected = HashSet::new();
+        expected.insert(format!("{}\x1F{}", "t", "price"));
+        expected.insert("name".to_string());
+        assert_eq!(extract_columns("SELECT \"T\".[Price], \"Name\" FROM t", &options).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_sql_assigned_and_using_columns() {
+        let options = ExtractOptions::default();
+        let columns = |sql_query| extract_columns(sql_query, &options).unwrap();
+        let names = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<HashSet<String>>();
+        assert_eq!(columns("UPDATE t SET qty = 0, (a, b) = (1, 2)"), names(&["qty", "a", "b"]));
+        assert_eq!(columns("INSERT INTO t (id, total) VALUES (1, 2) ON CONFLICT (id) DO UPDATE SET total = 3"), names(&["id", "total"]));
+        assert_eq!(columns("SELECT * FROM a JOIN b USING (id)"), names(&["id"]));
+        let identifiers = all_identifiers("UPDATE t SET qty = 0", &options).unwrap();
+        assert_eq!(identifiers, names(&["t", "qty"]));
+        let identifiers = all_identifiers("CREATE TRIGGER tr AFTER DELETE ON a BEGIN INSERT INTO log (id) SELECT 1; END", &options).unwrap();
+        assert_eq!(identifiers, names(&["a", "log", "id"]));
+    }
+
+    #[test]
+    fn test_sql_all_identifiers() {
+        let sql_query = "SELECT o.total, name FROM ext.orders o JOIN users ON o.user_id = users.id";
+        let identifiers = all_identifiers(sql_query, &ExtractOptions::default()).unwrap();
+        let mut expected = HashSet::new();
+        for identifier in ["ext", "orders", "users", "o", "total", "name", "user_id", "id"] {
+            expected.insert(identifier.to_string());
+        }
+        assert_eq!(identifiers, expected);
+        let options = ExtractOptions { normalize_case: true, strip_quotes: true, ..ExtractOptions::default() };
+        let identifiers = all_identifiers("SELECT \"Col\" FROM \"Tab\" AS \"X\"", &options).unwrap();
+        let mut expected = HashSet::new();
+        expected.insert("col".to_string());
+        expected.insert("tab".to_string());
+        expected.insert("x".to_string());
+        assert_eq!(identifiers, expected);
+        let sql_query = "WITH recent AS (SELECT 1) SELECT a AS total, b FROM recent, (SELECT 2) AS d, (t JOIN u) AS v";
+        let identifiers = all_identifiers(sql_query, &ExtractOptions::default()).unwrap();
+        let mut expected = HashSet::new();
+        for identifier in ["recent", "a", "total", "b", "d", "t", "u", "v"] {
+            expected.insert(identifier.to_string());
+        }
+        assert_eq!(identifiers, expected);
+    }
+
+    #[test]
+    fn test_sql_update_from() {
+        let sql_query = "UPDATE inventory\nSET qty = s.qty, updated = (SELECT now FROM clock)\nFROM shipments s\nWHERE inventory.id = s.id AND s.id IN (SELECT id FROM approved)";
+        let mut expected = HashSet::new();
+        for name in ["inventory", "clock", "shipments", "approved"] {
+            expected.insert(name.to_string());
+        }
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+    }
+
+    #[test]
+    fn test_sql_delete() {
+        let sql_query = "WITH old AS (SELECT id FROM archive) DELETE FROM ext.t WHERE id IN (SELECT id FROM u) OR id IN old \
+                         RETURNING (SELECT name FROM names)";
+        let mut expected = HashSet::new();
+        for name in ["archive", "ext\x1Ft", "u", "names"] {
+            expected.insert(name.to_string());
+        }
+        let options = ExtractOptions { fail_on_unsupported: true, ..ExtractOptions::default() };
+        assert_eq!(extract_tables(sql_query, &options).unwrap(), expected);
+        let access = extract_table_access(sql_query, &options).unwrap();
+        let mut writes = HashSet::new();
+        writes.insert("ext\x1Ft".to_string());
+        assert_eq!(access.writes, writes);
+        assert!(!access.reads.contains("ext\x1Ft"));
+    }
+
+    #[test]
+    fn test_sql_update_qualified_table() {
+        let sql_query = "UPDATE main.inventory SET qty = 0 WHERE id IN (SELECT id FROM ext.sold)";
+        let mut expected = HashSet::new();
+        expected.insert("main\x1Finventory".to_string());
+        expected.insert("ext\x1Fsold".to_string());
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+        let access = extract_table_access(sql_query, &ExtractOptions::default()).unwrap();
+        assert!(access.writes.contains("main\x1Finventory"));
+    }
+
+    #[test]
+    fn test_sql_escape_separator() {
+        let sql_query = "SELECT * FROM \"a\x1Fb\" JOIN ext.\"c\\x1Fd\" JOIN \"e\x1Ff\".g";
+        let options = ExtractOptions { strip_quotes: true, escape_separator: true, ..ExtractOptions::default() };
+        let table_names = extract_tables(sql_query, &options).unwrap();
+        let mut expected = HashSet::new();
+        expected.insert("a\\x1Fb".to_string());
+        expected.insert("ext\x1Fc\\\\x1Fd".to_string());
+        expected.insert("e\\x1Ff\x1Fg".to_string());
+        assert_eq!(table_names, expected);
+        let mut parts: Vec<Vec<String>> = table_names.iter().map(|name| split_table_name(name)).collect();
+        parts.sort();
+        let expected: Vec<Vec<String>> = vec![
+            vec!["a\x1Fb".to_string()],
+            vec!["e\x1Ff".to_string(), "g".to_string()],
+            vec!["ext".to_string(), "c\\x1Fd".to_string()],
+        ];
+        assert_eq!(parts, expected);
+        let options = ExtractOptions { normalize_case: true, ..options };
+        let table_names = extract_tables("SELECT * FROM \"A\x1FB\".\"C\\D\"", &options).unwrap();
+        let name = table_names.iter().next().unwrap();
+        assert_eq!(name, "a\\x1Fb\x1Fc\\\\d");
+        assert_eq!(split_table_name(name), vec!["a\x1Fb".to_string(), "c\\d".to_string()]);
+    }
+
+    #[test]
+    fn test_sql_update_or_conflict() {
+        for conflict in ["REPLACE", "IGNORE", "ROLLBACK", "ABORT", "FAIL"] {
+            let sql_query = format!("UPDATE OR {} t SET x = (SELECT y FROM src) WHERE id = 1", conflict);
+            let mut expected = HashSet::new();
+            expected.insert("t".to_string());
+            expected.insert("src".to_string());
+            assert_eq!(parse_sql_command_for_table_names(&sql_query), expected, "UPDATE OR {}", conflict);
+        }
+    }
+
+    #[test]
+    fn test_sql_with_cte_filtered() {
+        let sql_query = "WITH c AS (SELECT * FROM base), d AS (SELECT * FROM c) SELECT * FROM d JOIN other WHERE x IN c";
+        let mut expected = HashSet::new();
+        expected.insert("base".to_string());
+        expected.insert("other".to_string());
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+    }
+
+    #[test]
+    fn test_sql_with_insert_values() {
+        let sql_query = "WITH c AS (SELECT x FROM base) INSERT INTO t VALUES ((SELECT x FROM c))";
+        let mut expected = HashSet::new();
+        expected.insert("base".to_string());
+        expected.insert("t".to_string());
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+    }
+
+    #[test]
+    fn test_sql_with_nested_cte() {
+        let sql_query = "WITH outer AS (WITH inner AS (SELECT * FROM base) SELECT * FROM inner) SELECT * FROM outer";
+        let mut expected = HashSet::new();
+        expected.insert("base".to_string());
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+        // `inner` is only in scope inside the CTE that defines it
+        let sql_query = "WITH o AS (WITH i AS (SELECT * FROM base) SELECT * FROM i) SELECT * FROM o JOIN i";
+        expected.insert("i".to_string());
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+    }
+
+    #[test]
+    fn test_sql_with_recursive_cte() {
+        let sql_query = "WITH RECURSIVE r(n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM r WHERE n < 10) SELECT * FROM r, main.r";
+        let mut expected = HashSet::new();
+        expected.insert("main\x1Fr".to_string());
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+    }
+
+    #[test]
+    fn test_sql_with_json_arrow_operators() {
+        let sql_query = "SELECT data -> '$.name' FROM docs WHERE data ->> '$.id' IN (SELECT id FROM allow) \
+                         AND (SELECT data -> '$.x' FROM meta) ->> '$.y' = 1";
+        let mut expected = HashSet::new();
+        expected.insert("docs".to_string());
+        expected.insert("allow".to_string());
+        expected.insert("meta".to_string());
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+    }
+
+    #[test]
+    fn test_assert_tables_eq() {
+        assert_tables_eq("SELECT * FROM \"Orders\" JOIN main.items JOIN ext.users", &["orders", "items", "EXT.users"]).unwrap();
+        assert_tables_eq("SELECT * FROM main.t", &["main.t"]).unwrap();
+        assert_tables_eq("SELECT * FROM \"Orders\" JOIN [ext].\"Users\"", &["\"Orders\"", "`ext`.users"]).unwrap();
+        assert_tables_eq("SELECT * FROM temp.t JOIN \"a.b\"", &["TEMP.t", "\"a.b\""]).unwrap();
+        assert!(matches!(assert_tables_eq("SELECT * FROM", &[]), Err(TablesMismatch::Parse(ParseError::SyntaxError { .. }))));
+    }
+
+    #[test]
+    fn test_assert_tables_eq_invalid_names() {
+        for name in ["t; DROP TABLE x", "t WHERE 1", "", "a.", ".t", "a.b.c", "t u", "f(1)", "1", "?"] {
+            assert_eq!(assert_tables_eq("SELECT * FROM t", &[name]), Err(TablesMismatch::InvalidName(name.to_string())), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_assert_tables_eq_mismatch() {
+        let mismatch = assert_tables_eq("SELECT * FROM a JOIN b JOIN ext.e", &["a", "d", "c"]).unwrap_err();
+        let expected = TablesMismatch::Differ {
+            missing: vec!["c".to_string(), "d".to_string()],
+            unexpected: vec!["b".to_string(), "ext.e".to_string()],
+        };
+        assert_eq!(mismatch, expected);
+        assert_eq!(mismatch.to_string(), "tables differ: missing [c, d], unexpected [b, ext.e]");
+    }
+
+    #[test]
+    fn test_sql_cte_resolution() {
+        let sql_query = "WITH recent AS (SELECT * FROM events JOIN users WHERE x IN (SELECT x FROM flags)), \
+                         top AS (SELECT * FROM Recent JOIN ranks), \
+                         r(n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM r, top) \
+                         SELECT * FROM top, r";
+        let resolution = cte_resolution(sql_query, &ExtractOptions::default()).unwrap();
+        let set = |tables: &[&str]| -> HashSet<String> { tables.iter().map(|table| table.to_string()).collect() };
+        let mut expected = HashMap::new();
+        expected.insert("recent".to_string(), set(&["events", "users", "flags"]));
+        expected.insert("top".to_string(), set(&["events", "users", "flags", "ranks"]));
+        expected.insert("r".to_string(), set(&["events", "users", "flags", "ranks"]));
+        assert_eq!(resolution, expected);
+    }
+
+    #[test]
+    fn test_sql_cte_definitions() {
+        let sql_query = "WITH recent AS (SELECT * FROM events JOIN users), top AS (SELECT * FROM Recent JOIN ranks) SELECT * FROM top";
+        let resolution = cte_definitions(sql_query, &ExtractOptions::default()).unwrap();
+        let cte = |statement, name: &str, tables: &[&str]| CteResolution {
+            statement,
+            name: name.to_string(),
+            tables: tables.iter().map(|table| table.to_string()).collect(),
+        };
+        assert_eq!(resolution, vec![cte(1, "recent", &["events", "users"]), cte(1, "top", &["events", "users", "ranks"])]);
+        let sql_query = "WITH a AS (SELECT * FROM x) SELECT * FROM a; \
+                         WITH a AS (SELECT * FROM y), b AS (WITH a AS (SELECT * FROM z) SELECT * FROM a) SELECT * FROM a, b";
+        assert_eq!(cte_definitions(sql_query, &ExtractOptions::default()).unwrap(), vec![
+            cte(1, "a", &["x"]),
+            cte(2, "a", &["y"]),
+            cte(2, "b", &["z"]),
+            cte(2, "a", &["z"]),
+        ]);
+    }
+
+    #[test]
+    fn test_sql_with_replace_into_select() {
+        let sql_query = "WITH c AS (SELECT * FROM base) REPLACE INTO t SELECT * FROM c";
+        let mut expected = HashSet::new();
+        expected.insert("base".to_string());
+        expected.insert("t".to_string());
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+    }
+
+    #[test]
+    fn test_sql_create_table_as_select_access() {
+        let access = extract_table_access("CREATE TABLE summary AS SELECT * FROM events", &ExtractOptions::default()).unwrap();
+        let mut expected = TableAccess::default();
+        expected.writes.insert("summary".to_string());
+        expected.reads.insert("events".to_string());
+        assert_eq!(access, expected);
+    }
+
+    #[test]
+    fn test_sql_table_access() {
+        let sql_query = "INSERT INTO log SELECT * FROM log JOIN users;\nUPDATE users SET n = (SELECT count(*) FROM log);\nSELECT * FROM audit";
+        let access = extract_table_access(sql_query, &ExtractOptions::default()).unwrap();
+        let mut expected = TableAccess::default();
+        expected.writes.insert("log".to_string());
+        expected.writes.insert("users".to_string());
+        expected.reads.insert("log".to_string());
+        expected.reads.insert("users".to_string());
+        expected.reads.insert("audit".to_string());
+        assert_eq!(access, expected);
+        // reads and writes are spelled like the table `extract_tables` reports
+        let access = extract_table_access("INSERT INTO Log SELECT * FROM log", &ExtractOptions::default()).unwrap();
+        let mut expected = TableAccess::default();
+        expected.writes.insert("Log".to_string());
+        expected.reads.insert("Log".to_string());
+        assert_eq!(access, expected);
+    }
+
+    #[test]
+    fn test_sql_create_table_foreign_keys() {
+        let sql_query = "CREATE TABLE child (\n  id INTEGER PRIMARY KEY,\n  parent_id INTEGER REFERENCES parent(id) ON DELETE CASCADE ON UPDATE SET NULL,\n  owner_id INTEGER,\n  FOREIGN KEY (owner_id) REFERENCES owners(id) ON DELETE RESTRICT DEFERRABLE INITIALLY DEFERRED\n)";
+        let mut expected = HashSet::new();
+        expected.insert("child".to_string());
+        expected.insert("parent".to_string());
+        expected.insert("owners".to_string());
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+    }
+
+    #[test]
+    fn test_sql_create_temp_view() {
+        let sql_query = "CREATE TEMP VIEW recent AS SELECT * FROM orders WHERE id > (SELECT max(id) - 10 FROM main.orders)";
+        let mut expected = HashSet::new();
+        expected.insert("recent".to_string());
+        expected.insert("orders".to_string());
+        expected.insert("main\x1Forders".to_string());
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+        let options = ExtractOptions { qualify_temp: true, ..ExtractOptions::default() };
+        let access = extract_table_access(sql_query, &options).unwrap();
+        let mut expected = HashSet::new();
+        expected.insert("temp\x1Frecent".to_string());
+        assert_eq!(access.writes, expected);
+        let access = extract_table_access("CREATE TEMPORARY TABLE t (x); CREATE VIEW ext.v AS SELECT 1", &options).unwrap();
+        let mut expected = HashSet::new();
+        expected.insert("temp\x1Ft".to_string());
+        expected.insert("ext\x1Fv".to_string());
+        assert_eq!(access.writes, expected);
+    }
+
+    #[test]
+    fn test_sql_create_temp_trigger() {
+        let sql_query = "CREATE TEMP TRIGGER log_orders AFTER UPDATE ON orders WHEN new.qty > (SELECT cap FROM limits) BEGIN\n  INSERT INTO audit SELECT * FROM changes_view;\n  UPDATE totals SET n = n + 1 WHERE id IN (SELECT id FROM ids);\n  DELETE FROM stale WHERE id = old.id;\n  SELECT RAISE(IGNORE) FROM guards;\nEND";
+        let access = extract_table_access(sql_query, &ExtractOptions::default()).unwrap();
+        let mut expected = TableAccess::default();
+        for table in ["orders", "limits", "changes_view", "ids", "guards"] {
+            expected.reads.insert(table.to_string());
+        }
+        for table in ["audit", "totals", "stale"] {
+            expected.writes.insert(table.to_string());
+        }
+        assert_eq!(access, expected);
+    }
+
+    #[test]
+    fn test_sql_create_table_check_constraints() {
+        let sql_query = "CREATE TABLE items (qty INTEGER CHECK (qty >= 0) DEFAULT 1, price REAL, CHECK (price > 0))";
+        let mut expected = HashSet::new();
+        expected.insert("items".to_string());
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+        // SQLite rejects subqueries in CHECK when preparing, but they parse
+        let sql_query = "CREATE TABLE items (qty INTEGER CHECK (qty <= (SELECT max_qty FROM limits)), CHECK (EXISTS (SELECT 1 FROM allowed)))";
+        expected.insert("limits".to_string());
+        expected.insert("allowed".to_string());
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+    }
+
+    #[test]
+    fn test_sql_create_table_foreign_key_schema() {
+        let sql_query = "CREATE TABLE ext.child (parent_id INTEGER REFERENCES parent ON DELETE CASCADE)";
+        let mut expected = HashSet::new();
+        expected.insert(format!("{}\x1F{}", "ext", "child"));
+        expected.insert(format!("{}\x1F{}", "ext", "parent"));
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+    }
+
+    #[test]
+    fn test_sql_aliases_with_and_without_as() {
+        let options = ExtractOptions::default();
+        let mut expected = HashMap::new();
+        expected.insert("x".to_string(), "t".to_string());
+        assert_eq!(extract_aliases("SELECT x.a FROM t x", &options).unwrap(), expected);
+        assert_eq!(extract_aliases("SELECT x.a FROM t AS x", &options).unwrap(), expected);
+        // the last use of a reused alias wins
+        assert_eq!(extract_aliases("SELECT * FROM a x; SELECT * FROM t x", &options).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_sql_join_aliases() {
+        let sql_query = "Select m.title, r.id\n FROM Movies m\n INNER JOIN (\nSELECT rs.movie_id\n FROM Rooms r2 \n WHERE r2.seaats >= 50 \n ) AS r \n ON m.id = r.movide_id AND m.title != 'Batman';";
+        let mut expected = HashMap::new();
+        expected.insert("m".to_string(), "Movies".to_string());
+        expected.insert("r2".to_string(), "Rooms".to_string());
+        assert_eq!(extract_aliases(sql_query, &ExtractOptions::default()).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_sql_normalization() {
+        let sql_query = "SELECT *\nFROM main.Orders\nJOIN temp.t ON 1\nJOIN Items ON 1";
+        let options = ExtractOptions { normalize_case: true, normalize_schema: true, ..ExtractOptions::default() };
+        let mut expected = HashSet::new();
+        expected.insert("orders".to_string());
+        expected.insert(format!("{}\x1F{}", "temp", "t").to_string());
+        expected.insert("items".to_string());
+        assert_eq!(extract_tables(sql_query, &options).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_sql_table_signature() {
+        let options = ExtractOptions::default();
+        let signature = table_signature("SELECT * FROM Orders JOIN items ON 1 WHERE x IN (SELECT x FROM ext.Users)", &options).unwrap();
+        assert_eq!(signature, "ext\x1Fusers\x1Emain\x1Fitems\x1Emain\x1Forders");
+        let reordered = table_signature("select * from ext.users, main.ITEMS; insert into orders values (1)", &options).unwrap();
+        assert_eq!(reordered, signature);
+        let different = table_signature("SELECT * FROM orders JOIN items ON 1", &options).unwrap();
+        assert_ne!(different, signature);
+        let quoted = table_signature("SELECT * FROM \"Orders\" JOIN [items] ON 1 WHERE x IN (SELECT x FROM `ext`.'users')", &options).unwrap();
+        assert_eq!(quoted, signature);
+        let quoted_main = table_signature("SELECT * FROM \"main\".orders JOIN items ON 1", &options).unwrap();
+        assert_eq!(quoted_main, different);
+        // SQLite folds only ASCII, so these are two tables with two signatures
+        let upper = table_signature("SELECT * FROM \"Ä\"", &options).unwrap();
+        let lower = table_signature("SELECT * FROM \"ä\"", &options).unwrap();
+        assert_ne!(upper, lower);
+    }
+
+    #[test]
+    fn test_sql_ranked_tables() {
+        let sql_query = "SELECT * FROM orders JOIN users;\nUPDATE orders SET x = 1;\nSELECT * FROM Users u WHERE u.id IN (SELECT id FROM orders);\nSELECT * FROM audit";
+        let expected = vec![("orders".to_string(), 3), ("users".to_string(), 2), ("audit".to_string(), 1)];
+        assert_eq!(ranked_tables(sql_query, &ExtractOptions::default()).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_sql_table_references() {
+        let sql_query = "SELECT * FROM b JOIN a JOIN b;\nINSERT INTO c SELECT * FROM a WHERE x IN (SELECT x FROM c)";
+        let expected = vec!["b", "a", "b", "c", "a", "c"];
+        assert_eq!(table_references(sql_query, &ExtractOptions::default()).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_sql_ranked_tables_ties() {
+        let options = ExtractOptions { normalize_schema: true, ..ExtractOptions::default() };
+        let sql_query = "SELECT * FROM b, a, main.c, c";
+        let expected = vec![("c".to_string(), 2), ("a".to_string(), 1), ("b".to_string(), 1)];
+        assert_eq!(ranked_tables(sql_query, &options).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_sql_table_functions_skipped() {
+        let sql_query = "SELECT *\nFROM changes()\nJOIN json_each(o.data) j\nJOIN pragma_table_info('orders') p\nJOIN orders o";
+        let mut expected = HashSet::new();
+        expected.insert("orders".to_string());
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+    }
+
+    #[test]
+    fn test_sql_table_function_arguments() {
+        let sql_query = "SELECT *\nFROM json_each((SELECT data FROM documents WHERE id = 1))";
+        let mut expected = HashSet::new();
+        expected.insert("documents".to_string());
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+    }
+
+    #[test]
+    fn test_sql_custom_table_functions() {
+        let mut options = ExtractOptions::default();
+        options.table_functions.insert("my_split".to_string());
+        let sql_query = "SELECT * FROM t, My_Split('a,b'), json_each(t.doc) WHERE x IN my_split('c')";
+        let mut expected = HashSet::new();
+        expected.insert("t".to_string());
+        assert_eq!(extract_tables(sql_query, &options).unwrap(), expected);
+        expected.insert("My_Split".to_string());
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+    }
+
+    #[test]
+    fn test_sql_table_diff() {
+        let options = ExtractOptions { normalize_case: true, normalize_schema: true, ..ExtractOptions::default() };
+        let before = "SELECT * FROM orders JOIN Users ON 1 JOIN legacy_prices ON 1";
+        let after = "SELECT * FROM main.orders JOIN users ON 1 JOIN prices ON 1 WHERE x IN (SELECT x FROM discounts)";
+        let diff = table_diff(before, after, &options).unwrap();
+        let mut expected = TableDiff::default();
+        expected.added.insert("prices".to_string());
+        expected.added.insert("discounts".to_string());
+        expected.removed.insert("legacy_prices".to_string());
+        expected.common.insert("orders".to_string());
+        expected.common.insert("users".to_string());
+        assert_eq!(diff, expected);
+        assert!(matches!(table_diff(before, "SELECT * FROM", &options), Err(ParseError::SyntaxError { .. })));
+    }
+
+    #[test]
+    fn test_sql_has_dynamic_source() {
+        let options = ExtractOptions::default();
+        assert_eq!(has_dynamic_source("SELECT * FROM t WHERE id = ?", &options), Ok(false));
+        assert_eq!(has_dynamic_source("SELECT * FROM t, json_each(t.doc, '$.a')", &options), Ok(false));
+        assert_eq!(has_dynamic_source("SELECT * FROM generate_series(1, :n)", &options), Ok(true));
+        assert_eq!(has_dynamic_source("SELECT * FROM json_each((SELECT doc FROM t))", &options), Ok(true));
+        assert_eq!(has_dynamic_source("SELECT * FROM t WHERE x IN json_each(?1)", &options), Ok(true));
+        assert_eq!(has_dynamic_source("SELECT * FROM my_vtab(?)", &options), Ok(true));
+        assert_eq!(has_dynamic_source("SELECT * FROM t WHERE x IN my_vtab((SELECT 1))", &options), Ok(true));
+        assert_eq!(has_dynamic_source("SELECT * FROM my_vtab(1)", &options), Ok(false));
+    }
+
+    #[test]
+    fn test_sql_table_function_alias() {
+        let sql_query = "SELECT j.value, j.key FROM t, json_each(t.doc) AS j WHERE j.type = 'integer'";
+        let mut expected = HashSet::new();
+        expected.insert("t".to_string());
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+        let options = ExtractOptions { include_functions: true, ..ExtractOptions::default() };
+        expected.insert("json_each".to_string());
+        assert_eq!(extract_tables(sql_query, &options).unwrap(), expected);
+        assert_eq!(extract_aliases(sql_query, &options).unwrap().get("j").map(String::as_str), Some("json_each"));
+        // like derived tables, table functions take no column list after the alias
+        let sql_query = "SELECT * FROM json_each('[1,2]') AS j(value, key)";
+        assert!(matches!(extract_tables(sql_query, &options), Err(ParseError::SyntaxError { line: 1, column: 39, .. })));
+    }
+
+    #[test]
+    fn test_sql_include_functions() {
+        let sql_query = "SELECT *\nFROM generate_series(1, 10)\nJOIN my_vtab(1) v";
+        let options = ExtractOptions { include_functions: true, ..ExtractOptions::default() };
+        let mut expected = HashSet::new();
+        expected.insert("generate_series".to_string());
+        expected.insert("my_vtab".to_string());
+        assert_eq!(extract_tables(sql_query, &options).unwrap(), expected);
+
+        let mut expected = HashSet::new();
+        expected.insert("my_vtab".to_string());
+        assert_eq!(extract_tables(sql_query, &ExtractOptions::default()).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_sql_multiple_statements() {
+        let sql_query = "SELECT * FROM a;\nSELECT * FROM b JOIN a;\nEXPLAIN SELECT * FROM c";
+        let mut expected = HashSet::new();
+        expected.insert("a".to_string());
+        expected.insert("b".to_string());
+        expected.insert("c".to_string());
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+    }
+
+    #[test]
+    fn test_sql_parenthesized_statement() {
+        let sql_query = "(SELECT * FROM t);\n((SELECT * FROM u WHERE x IN (SELECT y FROM v)))";
+        let mut expected = HashSet::new();
+        expected.insert("t".to_string());
+        expected.insert("u".to_string());
+        expected.insert("v".to_string());
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+        assert!(matches!(extract_tables("(SELECT * FROM t) x", &ExtractOptions::default()), Err(ParseError::SyntaxError { .. })));
+        let err = extract_tables("(SELECT 1);\nSELECT * FROM WHERE", &ExtractOptions::default()).unwrap_err();
+        assert!(matches!(err, ParseError::SyntaxError { line: 2, .. }), "{:?}", err);
+    }
+
+    #[test]
+    fn test_sql_minimal_statements() {
+        let sql_queries = [
+            "SELECT 1",
+            "SELECT *",
+            "SELECT DISTINCT 1 FROM (SELECT 1)",
+            "VALUES (1)",
+            "SELECT count(*) OVER () FROM (VALUES (1))",
+            "SELECT * FROM (SELECT * FROM (SELECT 1))",
+            "SELECT x FROM t WINDOW w AS ()",
+            "SELECT * FROM t GROUP BY 1",
+            "INSERT INTO t DEFAULT VALUES",
+            "CREATE TABLE t (x)",
+            "CREATE TABLE t AS SELECT 1",
+            "UPDATE t SET x = NULL",
+            "WITH c AS (SELECT 1) SELECT * FROM c",
+            ";;",
+        ];
+        for sql_query in sql_queries {
+            assert!(extract_tables(sql_query, &ExtractOptions::default()).is_ok(), "{}", sql_query);
+            assert!(explain_extraction(sql_query, &ExtractOptions::default()).is_ok(), "{}", sql_query);
+            extract_with_diagnostics(sql_query);
+        }
+    }
+
+    #[test]
+    fn test_sql_comment_only() {
+        for sql_query in ["-- nothing here", "/* disabled: SELECT * FROM a; */", "-- a\n/* b */\n;\n  -- c\n", ""] {
+            assert_eq!(extract_tables(sql_query, &ExtractOptions::default()), Ok(HashSet::new()), "{:?}", sql_query);
+        }
+    }
+
+    #[test]
+    fn test_sql_batch_with_maintenance_statements() {
+        let sql_query = "SELECT * FROM a; VACUUM; PRAGMA optimize; BEGIN; SELECT * FROM b; COMMIT; VACUUM main INTO 'backup.db';";
+        let mut expected = HashSet::new();
+        expected.insert("a".to_string());
+        expected.insert("b".to_string());
+        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+        let options = ExtractOptions { fail_on_unsupported: true, ..ExtractOptions::default() };
+        assert_eq!(extract_tables("SELECT * FROM a; VACUUM; SELECT * FROM b;", &options), Ok(expected));
+    }
+
+    #[test]
+    fn test_sql_syntax_error() {
+        let sql_query = "SELECT * FROM a;\nSELECT * FROM WHERE";
+        match extract_tables(sql_query, &ExtractOptions::default()) {
+            Err(ParseError::SyntaxError { line, .. }) => assert_eq!(line, 2),
+            result => panic!("expected a syntax error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_sql_postgres_syntax_error() {
+        let sql_query = "SELECT DISTINCT ON (customer) * FROM orders ORDER BY customer";
+        match extract_tables(sql_query, &ExtractOptions::default()) {
+            Err(ParseError::SyntaxError { message, line, column }) => {
+                assert!(message.ends_with("(DISTINCT ON is PostgreSQL syntax, SQLite only supports plain DISTINCT)"), "{}", message);
+                assert_eq!((line, column), (1, 19));
+            },
+            result => panic!("expected a syntax error, got {:?}", result),
+        }
+        match extract_tables("SELECT * FROM t WHERE name ILIKE 'a%'", &ExtractOptions::default()) {
+            Err(ParseError::SyntaxError { message, .. }) => assert!(message.contains("ILIKE is PostgreSQL syntax"), "{}", message),
+            result => panic!("expected a syntax error, got {:?}", result),
+        }
+        // `ilike` is a valid column or table name, the error is the missing WHERE expression
+        for sql_query in ["SELECT ilike FROM t WHERE", "SELECT * FROM ilike WHERE"] {
+            match extract_tables(sql_query, &ExtractOptions::default()) {
+                Err(ParseError::SyntaxError { message, .. }) => assert!(!message.contains("PostgreSQL"), "{}", message),
+                result => panic!("expected a syntax error, got {:?}", result),
+            }
+        }
+    }
+
+    #[test]
+    fn test_sql_max_input_bytes() {
+        let sql_query = "SELECT * FROM a";
+        let options = ExtractOptions { max_input_bytes: Some(sql_query.len()), ..ExtractOptions::default() };
+        assert!(extract_tables(sql_query, &options).is_ok());
+        let options = ExtractOptions { max_input_bytes: Some(sql_query.len() - 1), ..ExtractOptions::default() };
+        assert_eq!(extract_tables(sql_query, &options), Err(ParseError::InputTooLarge { size: 15, limit: 14 }));
+    }
+
+    #[test]
+    fn test_sql_fail_on_unsupported() {
+        let sql_query = "SELECT * FROM a;\nPRAGMA foreign_keys = ON";
+        let mut expected = HashSet::new();
+        expected.insert("a".to_string());
+        assert_eq!(extract_tables(sql_query, &ExtractOptions::default()).unwrap(), expected);
+        let options = ExtractOptions { fail_on_unsupported: true, ..ExtractOptions::default() };
+        assert_eq!(extract_tables(sql_query, &options), Err(ParseError::Unsupported { statement: "PRAGMA".to_string() }));
+    }
+
+    #[test]
+    fn test_unquoted_keyword() {
+        assert!(is_unquoted_keyword("order"));
+        assert!(is_unquoted_keyword("SELECT"));
+        assert!(!is_unquoted_keyword("\"order\""));
+        assert!(!is_unquoted_keyword("[select]"));
+        assert!(!is_unquoted_keyword("orders"));
+    }
+
+    #[test]
+    fn test_sql_keyword_table_names() {
+        let sql_query = "SELECT *\nFROM key\nJOIN \"order\" ON 1\nJOIN temp.`select` ON 1\nJOIN temp.orders ON 1\nJOIN main.items ON 1\nJOIN action.log ON 1";
+        let expected = vec![format!("{}\x1F{}", "action", "log"), "key".to_string()];
+        assert_eq!(keyword_table_names(sql_query, &ExtractOptions::default()).unwrap(), expected);
+        let options = ExtractOptions { strip_quotes: true, ..ExtractOptions::default() };
+        assert_eq!(keyword_table_names("SELECT * FROM \"order\", [key]", &options).unwrap(), Vec::<String>::new());
+    }
+}
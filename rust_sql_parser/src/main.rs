@@ -1,166 +1,392 @@
-use fallible_iterator::FallibleIterator;
-use sqlite3_parser::lexer::sql::Parser;
-use sqlite3_parser::ast::{Cmd, FromClause, OneSelect, Stmt, Select, SelectBody, SelectTable, QualifiedName};
-use std::collections::HashSet;
-use std::str;
-
-
-pub fn parse_sql_command_for_table_names(sql_query: &str) -> HashSet<std::string::String> {
-    let mut parser = Parser::new(sql_query.as_bytes());
-    let cmd = parser.next();
-    if let Ok(Some(cmd)) = cmd {
-        match cmd {
-            Cmd::Stmt(Stmt::Select(select)) => {
-                let qualified_table_names = extract_table_names(&select);
-                let table_names = extract_table_name_strings(&qualified_table_names);
-                return table_names;
-            },
-            Cmd::Explain(_) | Cmd::ExplainQueryPlan(_) => todo!(),
-            _ => todo!()
+use regex::Regex;
+use rust_sql_parser::{count_per_schema, explain_extraction, extract_table_access, format_table_access, format_table_list, format_tables, join_count, keyword_table_names, rank_counts, ranked_tables, scan_comment_hints, table_references, FormatOptions, OutputFormat, ParseError, StatementReader, TableAccess, DEFAULT_HINT_PATTERN};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+
+const USAGE: &str = r#"Usage: rust_sql_parser [--include-functions] [--table-function <name>]... [--warn-keyword-tables] [--progress <n>] [--max-input-bytes <n>] [--normalize-case] [--normalize-schema] [--strip-quotes] [--qualify-temp] [--escape-separator] [--fail-on-unsupported] [--scan-comment-hints] [--hint-pattern <regex>] [--filter <regex>] [--count] [--count-per-schema] [--default-schema <name>] [--ranked] [--join-count] [--explain-extraction] [--dedup-across-statements on|off] [--format comma|lines|json|env|markdown] [--var <name>] [--separator <s>] [--sort] ([--] "<sql_query>" | --file <path>)"#;
+
+const EXIT_FAILURE: i32 = 1;
+const EXIT_SYNTAX_ERROR: i32 = 2;
+const EXIT_UNSUPPORTED: i32 = 3;
+const EXIT_INPUT_TOO_LARGE: i32 = 4;
+const EXIT_KEYWORD_TABLES: i32 = 5;
+
+enum CliError {
+    Usage(String),
+    Io(io::Error),
+    Parse(ParseError),
+    /// Statements of a `--file` input that failed, already reported on stderr.
+    Statements(Vec<ParseError>),
+    KeywordTables(usize),
+}
+
+impl CliError {
+    fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Parse(err) => parse_error_exit_code(err),
+            CliError::Statements(errors) => errors.first().map_or(EXIT_FAILURE, parse_error_exit_code),
+            CliError::KeywordTables(_) => EXIT_KEYWORD_TABLES,
+            CliError::Usage(_) | CliError::Io(_) => EXIT_FAILURE,
         }
-    };
-    HashSet::new()
+    }
 }
 
-fn extract_table_name_strings(qualified_names: &[QualifiedName]) -> HashSet<String> {
-   qualified_names
-       .iter()
-       .map(|qn| {
-           if let Some(db_name) = &qn.db_name {
-               format!("{}\x1F{}", db_name.0, qn.name.0)
-           } else {
-               qn.name.0.clone()
-           }
-       })
-       .collect()
+fn parse_error_exit_code(err: &ParseError) -> i32 {
+    match err {
+        ParseError::SyntaxError { .. } => EXIT_SYNTAX_ERROR,
+        ParseError::Unsupported { .. } => EXIT_UNSUPPORTED,
+        ParseError::InputTooLarge { .. } => EXIT_INPUT_TOO_LARGE,
+        ParseError::Io { .. } => EXIT_FAILURE,
+    }
 }
 
-fn extract_table_names(select: &Select) -> Vec<QualifiedName> {
-    let mut table_names = Vec::new();
-    extract_table_names_from_select(select, &mut table_names);
-    table_names
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Usage(message) => write!(f, "{}", message),
+            CliError::Io(err) => write!(f, "{}", err),
+            CliError::Parse(err) => write!(f, "{}", err),
+            CliError::Statements(errors) => write!(f, "{} statement(s) failed to parse", errors.len()),
+            CliError::KeywordTables(count) => write!(f, "{} table name(s) should be quoted", count),
+        }
+    }
 }
 
-fn extract_table_names_from_select(select: &Select, table_names: &mut Vec<QualifiedName>) {
-    extract_table_names_from_select_body(&select.body, table_names);
+impl From<String> for CliError {
+    fn from(message: String) -> CliError {
+        CliError::Usage(message)
+    }
 }
 
-fn extract_table_names_from_select_body(body: &SelectBody, table_names: &mut Vec<QualifiedName>) {
-    extract_table_names_from_one_select(&body.select, table_names);
-    if let Some(compounds) = &body.compounds {
-        for compound in compounds {
-            extract_table_names_from_one_select(&compound.select, table_names);
-        }
+impl From<io::Error> for CliError {
+    fn from(err: io::Error) -> CliError {
+        CliError::Io(err)
     }
 }
 
-fn extract_table_names_from_one_select(one_select: &OneSelect, table_names: &mut Vec<QualifiedName>) {
-    match one_select {
-        OneSelect::Select { from, .. } => {
-            if let Some(from_clause) = from {
-                extract_table_names_from_from_clause(from_clause, table_names);
+impl From<ParseError> for CliError {
+    fn from(err: ParseError) -> CliError {
+        CliError::Parse(err)
+    }
+}
+
+enum Input {
+    Query(String),
+    /// A file streamed statement by statement, `-` for stdin.
+    File(String),
+}
+
+struct CliArgs {
+    input: Input,
+    options: FormatOptions,
+    warn_keyword_tables: bool,
+    progress: Option<usize>,
+    count: bool,
+    /// Count tables per schema, unqualified ones under this schema.
+    count_per_schema: Option<String>,
+    ranked: bool,
+    join_count: bool,
+    explain_extraction: bool,
+    /// List every table reference in order instead of each table once.
+    keep_duplicates: bool,
+    /// Best-effort scan of comments for table hints, on top of the parsed tables.
+    comment_hints: Option<Regex>,
+    /// Only report the tables matching this pattern, see `filter_matches`.
+    filter: Option<Regex>,
+}
+
+/// Whether `filter` matches the table part of `name` or its `schema.table`
+/// form, so that `^audit_` keeps `ext.audit_log` and `^ext\.` keeps it too.
+fn filter_matches(filter: &Regex, name: &str) -> bool {
+    match name.split_once('\x1F') {
+        Some((schema, table)) => filter.is_match(table) || filter.is_match(&format!("{}.{}", schema, table)),
+        None => filter.is_match(name),
+    }
+}
+
+impl CliArgs {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<CliArgs, String> {
+        let mut input = None;
+        let mut options = FormatOptions::default();
+        let mut warn_keyword_tables = false;
+        let mut progress = None;
+        let mut count = false;
+        let mut per_schema = false;
+        let mut default_schema = "main".to_string();
+        let mut ranked = false;
+        let mut join_count = false;
+        let mut explain_extraction = false;
+        let mut keep_duplicates = false;
+        let mut scan_comment_hints = false;
+        let mut hint_pattern = DEFAULT_HINT_PATTERN.to_string();
+        let mut filter = None;
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                // everything after `--` is the query, even if it starts with `--`
+                if let Some(query) = args.next() {
+                    if input.is_some() {
+                        return Err(format!("Unexpected argument {}. {}", query, USAGE));
+                    }
+                    input = Some(Input::Query(query));
+                }
+                if let Some(arg) = args.next() {
+                    return Err(format!("Unexpected argument {}. {}", arg, USAGE));
+                }
+                break;
+            }
+            match arg.as_str() {
+                "--include-functions" => options.extract.include_functions = true,
+                "--table-function" => {
+                    options.extract.table_functions.insert(option_value(&mut args, &arg)?);
+                },
+                "--warn-keyword-tables" => warn_keyword_tables = true,
+                "--normalize-case" => options.extract.normalize_case = true,
+                "--normalize-schema" => options.extract.normalize_schema = true,
+                "--strip-quotes" => options.extract.strip_quotes = true,
+                "--qualify-temp" => options.extract.qualify_temp = true,
+                "--escape-separator" => options.extract.escape_separator = true,
+                "--fail-on-unsupported" => options.extract.fail_on_unsupported = true,
+                "--count" => count = true,
+                "--count-per-schema" => per_schema = true,
+                "--default-schema" => default_schema = option_value(&mut args, &arg)?,
+                "--ranked" => ranked = true,
+                "--join-count" => join_count = true,
+                "--explain-extraction" => explain_extraction = true,
+                "--dedup-across-statements" => {
+                    keep_duplicates = match option_value(&mut args, &arg)?.as_str() {
+                        "on" => false,
+                        "off" => true,
+                        value => return Err(format!("--dedup-across-statements expects on or off, got {}", value)),
+                    };
+                },
+                "--scan-comment-hints" => scan_comment_hints = true,
+                "--filter" => {
+                    let pattern = option_value(&mut args, &arg)?;
+                    filter = Some(Regex::new(&pattern).map_err(|err| format!("Invalid --filter: {}", err))?);
+                },
+                "--hint-pattern" => {
+                    scan_comment_hints = true;
+                    hint_pattern = option_value(&mut args, &arg)?;
+                },
+                "--format" => options.format = option_value(&mut args, &arg)?.parse()?,
+                "--sort" => options.sort = true,
+                "--separator" => options.separator = Some(option_value(&mut args, &arg)?),
+                "--var" => {
+                    let var = option_value(&mut args, &arg)?;
+                    if var.is_empty() || var.starts_with(|c: char| c.is_ascii_digit())
+                        || !var.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+                    {
+                        return Err(format!("--var expects a shell variable name, got {}", var));
+                    }
+                    options.env_var = Some(var);
+                },
+                "--progress" => progress = Some(positive_option_value(&mut args, &arg)?),
+                "--max-input-bytes" => options.extract.max_input_bytes = Some(positive_option_value(&mut args, &arg)?),
+                _ if input.is_some() => return Err(format!("Unexpected argument {}. {}", arg, USAGE)),
+                "--file" => input = Some(Input::File(option_value(&mut args, &arg)?)),
+                flag if is_option_name(flag) => return Err(format!("Unknown option {}. {}", flag, USAGE)),
+                _ => input = Some(Input::Query(arg)),
             }
-        },
-	OneSelect::Values(_) => {},
+        }
+        let input = input.ok_or_else(|| format!("Missing the sql query. {}", USAGE))?;
+        let comment_hints = if scan_comment_hints {
+            Some(Regex::new(&hint_pattern).map_err(|err| format!("Invalid --hint-pattern: {}", err))?)
+        } else {
+            None
+        };
+        if progress.is_some() && !matches!(input, Input::File(_)) {
+            return Err(format!("--progress requires --file. {}", USAGE));
+        }
+        let reports = [(count, "--count"), (per_schema, "--count-per-schema"), (join_count, "--join-count"), (ranked, "--ranked")];
+        let listings = [(options.format == OutputFormat::Markdown, "--format markdown"), (keep_duplicates, "--dedup-across-statements off")];
+        let modes: Vec<&str> = reports.iter().chain(&listings).filter(|(set, _)| *set).map(|(_, mode)| *mode).collect();
+        if modes.len() > 1 {
+            return Err(format!("{} can not be combined. {}", modes.join(" and "), USAGE));
+        }
+        // the reports print counts of their own and take no formatting options
+        let formatting = [
+            (options.format != OutputFormat::default(), "--format"),
+            (options.separator.is_some(), "--separator"),
+            (options.env_var.is_some(), "--var"),
+        ];
+        let report = reports.iter().find(|(set, _)| *set);
+        if let (Some((_, report)), Some((_, option))) = (report, formatting.iter().find(|(set, _)| *set)) {
+            return Err(format!("{} and {} can not be combined. {}", report, option, USAGE));
+        }
+        let count_per_schema = per_schema.then_some(default_schema);
+        Ok(CliArgs { input, options, warn_keyword_tables, progress, count, count_per_schema, ranked, join_count, explain_extraction, keep_duplicates, comment_hints, filter })
     }
 }
 
-fn extract_table_names_from_from_clause(from_clause: &FromClause, table_names:&mut Vec<QualifiedName>) {
-    if let Some(select_table) = &from_clause.select {
-        extract_table_names_from_select_table(select_table, table_names);
+/// Whether `arg` looks like an option, e.g. `--no-such-flag`, rather than a
+/// query starting with a `--` comment such as `-- list tables\nSELECT ...`.
+fn is_option_name(arg: &str) -> bool {
+    arg.strip_prefix("--").is_some_and(|name| name.starts_with(|c: char| c.is_ascii_alphabetic()) && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'))
+}
+
+fn option_value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, String> {
+    args.next().ok_or_else(|| format!("Missing a value for {}. {}", flag, USAGE))
+}
+
+fn positive_option_value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<usize, String> {
+    let value = option_value(args, flag)?;
+    match value.parse() {
+        Ok(number) if number > 0 => Ok(number),
+        _ => Err(format!("{} expects a positive number, got {}", flag, value)),
     }
-    if let Some(joins) = &from_clause.joins {
-        for join in joins {
-            extract_table_names_from_select_table(&join.table, table_names);
+}
+
+/// What the CLI reports about one or more statements.
+#[derive(Default)]
+struct Extraction {
+    /// References per table, including comment hints when they are scanned.
+    counts: HashMap<String, usize>,
+    /// The joins of each statement.
+    joins: Vec<usize>,
+    /// Every table reference in order, including comment hints when they are
+    /// scanned; only extracted for `--dedup-across-statements off`.
+    references: Vec<String>,
+    /// Only extracted for `--format markdown`.
+    access: TableAccess,
+    /// Only linted for `--warn-keyword-tables`.
+    keyword_tables: HashSet<String>,
+}
+
+impl Extraction {
+    fn merge(&mut self, other: Extraction) {
+        for (name, count) in other.counts {
+            *self.counts.entry(name).or_default() += count;
         }
+        self.joins.extend(other.joins);
+        self.references.extend(other.references);
+        self.access.reads.extend(other.access.reads);
+        self.access.writes.extend(other.access.writes);
+        self.keyword_tables.extend(other.keyword_tables);
     }
 }
 
-fn extract_table_names_from_select_table(select_table: &SelectTable, table_names:&mut Vec<QualifiedName>) {
-    match select_table {
-        SelectTable::Table(qualified_name, _, _) => {
-            add_unique_qualified_name(table_names, qualified_name);
-        },
-        SelectTable::TableCall(qualified_name, _, _,) => {
-            add_unique_qualified_name(table_names, qualified_name);
-        },
-        SelectTable::Select(select, _) => {
-            extract_table_names_from_select(select, table_names);
-        },
-        SelectTable::Sub(from_clause, _) => {
-            extract_table_names_from_from_clause(from_clause, table_names);
-        },
+/// Extracts what the CLI reports about `sql_query`. `statements_before`
+/// numbers the statements of a stream in the `--explain-extraction` trace.
+fn extract_statements(sql_query: &str, args: &CliArgs, statements_before: usize) -> Result<Extraction, ParseError> {
+    if args.explain_extraction {
+        for mut step in explain_extraction(sql_query, &args.options.extract)? {
+            step.statement += statements_before;
+            eprintln!("explain: {}", step);
+        }
     }
+    let mut counts: HashMap<String, usize> = ranked_tables(sql_query, &args.options.extract)?.into_iter().collect();
+    let mut references = Vec::new();
+    if args.keep_duplicates {
+        references = table_references(sql_query, &args.options.extract)?;
+    }
+    let mut access = TableAccess::default();
+    if args.options.format == OutputFormat::Markdown {
+        access = extract_table_access(sql_query, &args.options.extract)?;
+    }
+    if let Some(pattern) = &args.comment_hints {
+        for name in scan_comment_hints(sql_query, pattern, &args.options.extract) {
+            *counts.entry(name.clone()).or_default() += 1;
+            if args.keep_duplicates {
+                references.push(name.clone());
+            }
+            access.reads.insert(name);
+        }
+    }
+    let joins = if args.join_count { join_count(sql_query, &args.options.extract)? } else { Vec::new() };
+    let mut keyword_tables = HashSet::new();
+    if args.warn_keyword_tables {
+        keyword_tables.extend(keyword_table_names(sql_query, &args.options.extract)?);
+    }
+    Ok(Extraction { counts, joins, references, access, keyword_tables })
 }
 
-fn add_unique_qualified_name(table_names: &mut Vec<QualifiedName>, new_name: &QualifiedName) {
-    if !table_names.iter().any(|name| name == new_name) {
-        table_names.push(new_name.clone());
+/// Extracts the tables of each statement in `reader` as it is read, reporting
+/// statements that fail to parse on stderr instead of stopping. Stops with
+/// `ParseError::InputTooLarge` once the whole input exceeds `--max-input-bytes`,
+/// like `extract_from_reader`.
+fn extract_streaming(reader: impl BufRead, args: &CliArgs) -> Result<(Extraction, Vec<ParseError>), ParseError> {
+    let mut extraction = Extraction::default();
+    let mut errors = Vec::new();
+    let mut statements = StatementReader::new(reader);
+    if let Some(limit) = args.options.extract.max_input_bytes {
+        statements = statements.with_max_bytes(limit);
     }
+    for (index, statement) in statements.enumerate() {
+        let statement = statement?;
+        match extract_statements(&statement, args, index) {
+            Ok(statement_extraction) => extraction.merge(statement_extraction),
+            Err(err) => {
+                eprintln!("error: statement {}: {}", index + 1, err);
+                errors.push(err);
+            },
+        }
+        if let Some(every) = args.progress {
+            if (index + 1) % every == 0 {
+                let tables = extraction.counts.len();
+                eprintln!("progress: {} statements, {} distinct tables, {} errors", index + 1, tables, errors.len());
+            }
+        }
+    }
+    Ok((extraction, errors))
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let message = "<sql_query>";
-    let query = std::env::args().nth(1)
-        .expect(format!(r#"Missing the sql query. Usage: rust_sql_parser "{}""#, message).as_str());
-    let table_name_strings = parse_sql_command_for_table_names(&query); 
-    let table_names_joined = table_name_strings.into_iter().collect::<Vec<_>>().join(",");
-    println!("{}", table_names_joined);
-    Ok(())
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("error: {}", err);
+        std::process::exit(err.exit_code());
+    }
 }
 
-#[cfg(test)]
-pub mod tests {
-    use super::*;
-
-    #[test]
-    fn test_simple_sql() {
-        let sql_query = "SELECT *\nFROM bananas\nWHERE color = 'red'";
-        let mut expected = HashSet::new();
-        expected.insert("bananas".to_string());
-        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
-    }
-
-    #[test]
-    fn test_sql_join() {
-        let sql_query = "Select m.title, r.id\n FROM Movies m\n INNER JOIN (\nSELECT rs.movie_id\n FROM Rooms r2 \n WHERE r2.seaats >= 50 \n ) AS r \n ON m.id = r.movide_id AND m.title != 'Batman';";
-        let mut expected = HashSet::new();
-        expected.insert("Movies".to_string());
-        expected.insert("Rooms".to_string());    
-        assert_eq!(parse_sql_command_for_table_names(sql_query), expected); 
-    }
-    
-    #[test]
-    fn test_sql_union() {
-        let sql_query = "SELECT *\nFROM a\nUNION\nSELECT *\nFROM b";
-        let mut expected = HashSet::new();
-        expected.insert("a".to_string());
-        expected.insert("b".to_string());
-	assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
-    }
-
-    #[test]
-    fn test_sql_sub_query() {
-        let sql_query = "SELECT a.color\nFROM (\nSELECT b.color\nFROM bananas b\n) z JOIN apples a\nON a.color = b.color";
-	let mut expected = HashSet::new();
-        expected.insert("apples".to_string());
-        expected.insert("bananas".to_string());
-        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
-    }
-
-    #[test]
-    fn test_sql_backticks() {
-        let sql_query = "SELECT\n  *\nFROM\n  `hats` h\nWHERE\n  h.color == 'red'\nGROUP BY\n  h.color, h.material\nHAVING\n  COUNT(h.quantity) >= 200\nORDER BY\n  h.color DESC\nLIMIT\n  20\nOFFSET\n  10";
-        let mut expected = HashSet::new();
-        expected.insert("`hats`".to_string());
-        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
-    }
-
-    #[test]
-    fn test_sql_db_name() {
-        let sql_query = "SELECT *\nFROM apples.bananas\nWHERE color = 'red'";
-        let mut expected = HashSet::new();
-        expected.insert(format!("{}\x1F{}", "apples", "bananas").to_string());
-        assert_eq!(parse_sql_command_for_table_names(sql_query), expected);
+fn run() -> Result<(), CliError> {
+    let args = CliArgs::parse(std::env::args().skip(1))?;
+    let (mut extraction, errors) = match &args.input {
+        Input::Query(query) => (extract_statements(query, &args, 0)?, Vec::new()),
+        Input::File(path) if path == "-" => extract_streaming(io::stdin().lock(), &args)?,
+        Input::File(path) => extract_streaming(BufReader::new(File::open(path)?), &args)?,
+    };
+    if let Some(filter) = &args.filter {
+        extraction.counts.retain(|name, _| filter_matches(filter, name));
+        extraction.references.retain(|name| filter_matches(filter, name));
+        extraction.access.reads.retain(|name| filter_matches(filter, name));
+        extraction.access.writes.retain(|name| filter_matches(filter, name));
+        extraction.keyword_tables.retain(|name| filter_matches(filter, name));
     }
+    let table_name_strings: HashSet<String> = extraction.counts.keys().cloned().collect();
+    let mut keyword_names: Vec<String> = extraction.keyword_tables.iter().map(|name| name.replace('\x1F', ".")).collect();
+    keyword_names.sort();
+    if args.count {
+        println!("{}", table_name_strings.len());
+    } else if let Some(default_schema) = &args.count_per_schema {
+        let counts: Vec<String> = count_per_schema(&table_name_strings, default_schema)
+            .iter()
+            .map(|(schema, count)| format!("{}={}", schema, count))
+            .collect();
+        println!("{}", counts.join(" "));
+    } else if args.join_count {
+        for joins in &extraction.joins {
+            println!("{}", joins);
+        }
+    } else if args.ranked {
+        for (name, count) in rank_counts(extraction.counts) {
+            println!("{}\t{}", name, count);
+        }
+    } else if args.options.format == OutputFormat::Markdown {
+        println!("{}", format_table_access(&extraction.access, &args.options));
+    } else if args.keep_duplicates {
+        println!("{}", format_table_list(&extraction.references, &args.options));
+    } else {
+        println!("{}", format_tables(&table_name_strings, &args.options));
+    }
+    if args.warn_keyword_tables && !keyword_names.is_empty() {
+        for name in &keyword_names {
+            eprintln!("warning: table name {} is an unquoted SQLite keyword", name);
+        }
+        return Err(CliError::KeywordTables(keyword_names.len()));
+    }
+    if !errors.is_empty() {
+        return Err(CliError::Statements(errors));
+    }
+    Ok(())
 }
@@ -0,0 +1,351 @@
+use crate::{extract_tables, ExtractOptions, ParseError};
+use sqlite3_parser::dialect::TokenType;
+use sqlite3_parser::lexer::sql::Tokenizer;
+use sqlite3_parser::lexer::Scanner;
+use std::collections::HashSet;
+use std::io::{self, BufRead, BufReader, Read};
+
+/// Splits SQL read from `reader` into individual statements without
+/// buffering the whole input.
+///
+/// Statements end at a `;` outside of literals, comments and trigger bodies.
+/// Input the tokenizer can not make sense of is passed through as a single
+/// statement so that the parser gets to report the error.
+pub struct StatementReader<R> {
+    reader: R,
+    buffer: Vec<u8>,
+    eof: bool,
+    /// The line and column of the start of `buffer`.
+    position: (u64, usize),
+    start: (u64, usize),
+    bytes_read: usize,
+    max_bytes: Option<usize>,
+}
+
+impl<R: BufRead> StatementReader<R> {
+    pub fn new(reader: R) -> Self {
+        StatementReader { reader, buffer: Vec::new(), eof: false, position: (1, 1), start: (1, 1), bytes_read: 0, max_bytes: None }
+    }
+
+    /// Fails with an `io::ErrorKind::InvalidData` error wrapping
+    /// `ParseError::InputTooLarge` as soon as more than `limit` bytes are read,
+    /// without buffering the rest of the statement being read.
+    pub fn with_max_bytes(mut self, limit: usize) -> Self {
+        self.max_bytes = Some(limit);
+        self
+    }
+
+    /// The number of bytes read from the input so far.
+    pub fn bytes_read(&self) -> usize {
+        self.bytes_read
+    }
+
+    /// The 1-based line and column, in bytes, where the statement last
+    /// returned starts in the input.
+    pub fn statement_start(&self) -> (u64, usize) {
+        self.start
+    }
+
+    fn statement_end(&self) -> Option<usize> {
+        let mut scanner = Scanner::new(Tokenizer::new());
+        let mut tokens = 0;
+        // the tokens of an `EXPLAIN [QUERY PLAN]` prefix
+        let mut prefix = 0;
+        let mut in_trigger = false;
+        let mut depth = 0usize;
+        loop {
+            let (token_type, end) = match scanner.scan(&self.buffer) {
+                Ok((_, Some((_, token_type)), end)) => (token_type, end),
+                Ok((_, None, _)) | Err(_) => return None,
+            };
+            tokens += 1;
+            match token_type {
+                TokenType::TK_EXPLAIN if tokens == 1 => prefix = 1,
+                TokenType::TK_QUERY | TokenType::TK_PLAN if prefix > 0 && tokens == prefix + 1 => prefix += 1,
+                TokenType::TK_TRIGGER if tokens - prefix <= 3 => in_trigger = true,
+                TokenType::TK_BEGIN | TokenType::TK_CASE if in_trigger => depth += 1,
+                TokenType::TK_END if in_trigger => depth = depth.saturating_sub(1),
+                TokenType::TK_SEMI if depth == 0 => return Some(end),
+                _ => {},
+            }
+        }
+    }
+
+    /// Appends input up to the next newline to `buffer`, at most one buffer
+    /// of the underlying reader at a time so that `max_bytes` is checked before
+    /// a long line is held in memory.
+    fn read_line(&mut self) -> io::Result<()> {
+        loop {
+            let available = match self.reader.fill_buf() {
+                Ok(available) => available,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            if available.is_empty() {
+                self.eof = true;
+                return Ok(());
+            }
+            let newline = available.iter().position(|&b| b == b'\n');
+            let taken = newline.map_or(available.len(), |index| index + 1);
+            self.bytes_read += taken;
+            if let Some(limit) = self.max_bytes.filter(|&limit| self.bytes_read > limit) {
+                let err = ParseError::InputTooLarge { size: self.bytes_read, limit };
+                return Err(io::Error::new(io::ErrorKind::InvalidData, err));
+            }
+            self.buffer.extend_from_slice(&available[..taken]);
+            self.reader.consume(taken);
+            if newline.is_some() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Fails with an `io::ErrorKind::InvalidData` error rather than guess at
+    /// table names if the statement is not valid UTF-8.
+    fn take_statement(&mut self, end: usize) -> io::Result<String> {
+        let statement: Vec<u8> = self.buffer.drain(..end).collect();
+        let statement = String::from_utf8(statement).map_err(|err| {
+            let valid = &err.as_bytes()[..err.utf8_error().valid_up_to()];
+            let (line, column) = advance(self.position, std::str::from_utf8(valid).unwrap_or_default());
+            let message = format!("invalid UTF-8 at line {}, column {}", line, column);
+            io::Error::new(io::ErrorKind::InvalidData, message)
+        })?;
+        let leading = statement.len() - statement.trim_start().len();
+        self.start = advance(self.position, &statement[..leading]);
+        self.position = advance(self.position, &statement);
+        Ok(statement.trim().to_string())
+    }
+}
+
+/// Moves `position` past `text`.
+fn advance((mut line, mut column): (u64, usize), text: &str) -> (u64, usize) {
+    for b in text.bytes() {
+        if b == b'\n' {
+            line += 1;
+            column = 1;
+        } else {
+            column += 1;
+        }
+    }
+    (line, column)
+}
+
+impl<R: BufRead> Iterator for StatementReader<R> {
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<io::Result<String>> {
+        let mut rescan = true;
+        loop {
+            if rescan {
+                if let Some(end) = self.statement_end() {
+                    return Some(self.take_statement(end));
+                }
+            }
+            if self.eof {
+                return match self.take_statement(self.buffer.len()) {
+                    Ok(statement) if statement.is_empty() => None,
+                    result => Some(result),
+                };
+            }
+            let start = self.buffer.len();
+            if let Err(err) = self.read_line() {
+                return Some(Err(err));
+            }
+            // only a new `;` can complete the pending statement
+            rescan = self.buffer[start..].contains(&b';');
+        }
+    }
+}
+
+/// Extracts the tables of every statement read from `reader`, parsing each
+/// statement as soon as it is complete so the input is never held in memory
+/// as a whole. Stops at the first statement that fails, with a syntax error
+/// positioned in the whole input like `extract_tables` would.
+pub fn extract_from_reader<R: Read>(reader: R, options: &ExtractOptions) -> Result<HashSet<String>, ParseError> {
+    let mut table_names = HashSet::new();
+    let mut statements = StatementReader::new(BufReader::new(reader));
+    if let Some(limit) = options.max_input_bytes {
+        statements = statements.with_max_bytes(limit);
+    }
+    while let Some(statement) = statements.next() {
+        let statement = statement?;
+        match extract_tables(&statement, options) {
+            Ok(statement_tables) => table_names.extend(statement_tables),
+            Err(ParseError::SyntaxError { message, line, column }) => {
+                let (start_line, start_column) = statements.statement_start();
+                let column = if line == 1 { start_column + column - 1 } else { column };
+                return Err(ParseError::SyntaxError { message, line: start_line + line - 1, column });
+            },
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(table_names)
+}
+
+/// Blanks out parentheses wrapping a whole statement, as in `(SELECT * FROM t);`,
+/// which SQLite rejects but some generators emit. Offsets are kept so errors
+/// still point into the original text. Returns None if there is nothing to strip.
+pub(crate) fn strip_statement_parens(sql_query: &str) -> Option<String> {
+    let mut scanner = Scanner::new(Tokenizer::new());
+    let mut tokens = Vec::new();
+    loop {
+        match scanner.scan(sql_query.as_bytes()) {
+            Ok((start, Some((_, token_type)), _)) => tokens.push((token_type, start)),
+            Ok((_, None, _)) => break,
+            Err(_) => return None,
+        }
+    }
+    let mut stripped = sql_query.as_bytes().to_vec();
+    let mut changed = false;
+    for statement in tokens.split(|(token_type, _)| *token_type == TokenType::TK_SEMI) {
+        let mut statement = statement;
+        while let [(TokenType::TK_LP, open), inner @ .., (TokenType::TK_RP, close)] = statement {
+            let wraps_query = matches!(
+                inner.first(),
+                Some((TokenType::TK_SELECT | TokenType::TK_VALUES | TokenType::TK_WITH | TokenType::TK_LP, _))
+            );
+            if !wraps_query || !is_balanced(inner) {
+                break;
+            }
+            stripped[*open] = b' ';
+            stripped[*close] = b' ';
+            changed = true;
+            statement = inner;
+        }
+    }
+    // only ASCII parentheses were replaced, so the text is still UTF-8
+    if changed {
+        String::from_utf8(stripped).ok()
+    } else {
+        None
+    }
+}
+
+/// Explains a syntax error at `line` and `column` caused by another dialect's
+/// syntax, e.g. PostgreSQL's `DISTINCT ON`, which users moving between databases
+/// often do not expect. Only the token the parser rejected is explained, so
+/// that `ilike` used as a column or table name is not blamed for an error
+/// elsewhere.
+pub(crate) fn dialect_hint(sql_query: &str, line: u64, column: usize) -> Option<&'static str> {
+    // the parser reports the position just past the token it rejected
+    let error = offset(sql_query, line, column);
+    let mut scanner = Scanner::new(Tokenizer::new());
+    let mut previous = None;
+    while let Ok((_, Some((token, token_type)), end)) = scanner.scan(sql_query.as_bytes()) {
+        if end > error {
+            break;
+        }
+        if end == error {
+            return match (previous, token_type) {
+                (Some(TokenType::TK_DISTINCT), TokenType::TK_ON) => {
+                    Some("DISTINCT ON is PostgreSQL syntax, SQLite only supports plain DISTINCT")
+                },
+                (Some(_), TokenType::TK_ID) if token.eq_ignore_ascii_case(b"ilike") => {
+                    Some("ILIKE is PostgreSQL syntax, SQLite's LIKE is case-insensitive for ASCII")
+                },
+                _ => None,
+            };
+        }
+        previous = Some(token_type);
+    }
+    None
+}
+
+/// The byte offset of the 1-based `line` and `column`, in bytes, in `text`,
+/// or the length of `text` if the position is past its end.
+fn offset(text: &str, line: u64, column: usize) -> usize {
+    let mut line_start = 0;
+    for _ in 1..line {
+        match text[line_start..].find('\n') {
+            Some(newline) => line_start += newline + 1,
+            None => return text.len(),
+        }
+    }
+    (line_start + column.saturating_sub(1)).min(text.len())
+}
+
+/// Whether the parentheses of `tokens` pair up without closing an outer one.
+fn is_balanced(tokens: &[(TokenType, usize)]) -> bool {
+    let mut depth = 0usize;
+    for (token_type, _) in tokens {
+        match token_type {
+            TokenType::TK_LP => depth += 1,
+            TokenType::TK_RP if depth == 0 => return false,
+            TokenType::TK_RP => depth -= 1,
+            _ => {},
+        }
+    }
+    depth == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn statements(sql: &str) -> Vec<String> {
+        StatementReader::new(sql.as_bytes()).collect::<io::Result<_>>().unwrap()
+    }
+
+    #[test]
+    fn test_split_statements() {
+        let sql = "SELECT * FROM a;\nSELECT ';' FROM b; -- c;\nSELECT * FROM /* ; */ d";
+        assert_eq!(statements(sql), vec!["SELECT * FROM a;", "SELECT ';' FROM b;", "-- c;\nSELECT * FROM /* ; */ d"]);
+    }
+
+    #[test]
+    fn test_split_trigger_body() {
+        let sql = "CREATE TRIGGER tr AFTER INSERT ON a BEGIN\n  DELETE FROM b;\n  UPDATE c SET x = CASE WHEN 1 THEN 2 END;\nEND;\nSELECT * FROM d;";
+        let statements = statements(sql);
+        assert_eq!(statements.len(), 2);
+        assert!(statements[0].ends_with("END;"));
+        assert_eq!(statements[1], "SELECT * FROM d;");
+        for explain in ["EXPLAIN", "EXPLAIN QUERY PLAN"] {
+            let sql = format!("{} CREATE TEMP TRIGGER tr AFTER INSERT ON a BEGIN SELECT 1; END;\nSELECT * FROM d;", explain);
+            let statements = self::statements(&sql);
+            assert_eq!(statements.len(), 2, "{}", explain);
+            assert!(statements[0].ends_with("END;"));
+        }
+    }
+
+    #[test]
+    fn test_strip_statement_parens() {
+        assert_eq!(strip_statement_parens("((SELECT * FROM t));\n(VALUES (1))").as_deref(), Some("  SELECT * FROM t  ;\n VALUES (1) "));
+        assert_eq!(strip_statement_parens("(SELECT 1) UNION (SELECT 2)"), None);
+        assert_eq!(strip_statement_parens("SELECT (1)"), None);
+    }
+
+    #[test]
+    fn test_extract_from_reader() {
+        let cursor = io::Cursor::new("SELECT * FROM a;\nINSERT INTO b SELECT * FROM c;\nSELECT * FROM a JOIN d");
+        let table_names = extract_from_reader(cursor, &ExtractOptions::default()).unwrap();
+        let mut expected = HashSet::new();
+        expected.insert("a".to_string());
+        expected.insert("b".to_string());
+        expected.insert("c".to_string());
+        expected.insert("d".to_string());
+        assert_eq!(table_names, expected);
+    }
+
+    #[test]
+    fn test_extract_from_reader_errors() {
+        let options = ExtractOptions { max_input_bytes: Some(20), ..ExtractOptions::default() };
+        let cursor = io::Cursor::new("SELECT * FROM a;\nSELECT * FROM b;");
+        assert_eq!(extract_from_reader(cursor, &options), Err(ParseError::InputTooLarge { size: 33, limit: 20 }));
+        // a long statement is not buffered past the limit
+        let long = format!("SELECT * FROM t WHERE x IN ({})", vec!["1"; 1000].join(", "));
+        let mut statements = StatementReader::new(BufReader::with_capacity(16, long.as_bytes())).with_max_bytes(20);
+        let err = statements.next().unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(statements.bytes_read(), 32);
+        for sql in ["SELECT * FROM a;\n\n  -- b\n  SELECT * FROM WHERE;", "SELECT * FROM a;   SELECT * FROM WHERE;", "SELECT * FROM a; SELECT *\nFROM WHERE;"] {
+            let expected = crate::extract_tables(sql, &ExtractOptions::default()).unwrap_err();
+            assert!(matches!(expected, ParseError::SyntaxError { .. }));
+            assert_eq!(extract_from_reader(io::Cursor::new(sql), &ExtractOptions::default()), Err(expected), "{}", sql);
+        }
+        let cursor = io::Cursor::new(b"SELECT * FROM a;\nSELECT * FROM \xff;".to_vec());
+        match extract_from_reader(cursor, &ExtractOptions::default()) {
+            Err(ParseError::Io { message }) => assert!(message.contains("invalid UTF-8 at line 2, column 15"), "{}", message),
+            result => panic!("expected an I/O error, got {:?}", result),
+        }
+    }
+}
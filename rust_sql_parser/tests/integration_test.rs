@@ -1,5 +1,6 @@
 use std::process::Command;
 use assert_cmd::prelude::*;
+use predicates::prelude::*;
 
 
 #[test]
@@ -15,8 +16,464 @@ fn no_sql_query_failure() -> Result<(), Box<dyn std::error::Error>> {
 fn sql_query_success() -> Result<(), Box<dyn std::error::Error>> {
     Command::cargo_bin("rust_sql_parser")
         .expect("binary existst")
-        .args(&["Select * From apples"])
+        .args(["Select * From apples"])
         .assert()
         .success();
     Ok(())
 }
+
+#[test]
+fn include_functions_flag() -> Result<(), Box<dyn std::error::Error>> {
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--include-functions", "Select * From json_each('[1]')"])
+        .assert()
+        .success()
+        .stdout("json_each\n");
+    Ok(())
+}
+
+#[test]
+fn table_function_flag() -> Result<(), Box<dyn std::error::Error>> {
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--table-function", "my_split", "Select * From apples, my_split('a,b')"])
+        .assert()
+        .success()
+        .stdout("apples\n");
+    Ok(())
+}
+
+#[test]
+fn warn_keyword_tables_flag() -> Result<(), Box<dyn std::error::Error>> {
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--warn-keyword-tables", "Select * From key"])
+        .assert()
+        .code(5)
+        .stderr(predicates::str::contains("key is an unquoted SQLite keyword"));
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--warn-keyword-tables", r#"Select * From "order""#])
+        .assert()
+        .success();
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--warn-keyword-tables", "--strip-quotes", r#"Select * From "order" JOIN temp.orders JOIN main.items"#])
+        .assert()
+        .success();
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--warn-keyword-tables", "Select * From action.log"])
+        .assert()
+        .code(5)
+        .stderr(predicates::str::contains("warning: table name action.log is an unquoted SQLite keyword"));
+    Ok(())
+}
+
+#[test]
+fn query_starting_with_comment() -> Result<(), Box<dyn std::error::Error>> {
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["-- list tables\nSELECT * FROM apples"])
+        .assert()
+        .success()
+        .stdout("apples\n");
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--include-functions", "--", "--no-such-flag\nSELECT * FROM apples"])
+        .assert()
+        .success()
+        .stdout("apples\n");
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--", "SELECT * FROM apples", "--include-functions"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Unexpected argument --include-functions"));
+    Ok(())
+}
+
+#[test]
+fn progress_flag() -> Result<(), Box<dyn std::error::Error>> {
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--progress", "2", "--file", "-"])
+        .with_stdin()
+        .buffer("SELECT * FROM a;\nSELECT * FROM b;\nSELECT * FROM;\nSELECT * FROM a;\nSELECT * FROM c;\n")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("progress: 2 statements, 2 distinct tables, 0 errors"))
+        .stderr(predicates::str::contains("progress: 4 statements, 2 distinct tables, 1 errors"))
+        .stderr(predicates::str::contains("error: statement 3"));
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--progress", "2", "SELECT * FROM a"])
+        .assert()
+        .code(1)
+        .stderr(predicates::str::contains("--progress requires --file"));
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--file", "-"])
+        .with_stdin()
+        .buffer("EXPLAIN CREATE TEMP TRIGGER tr AFTER INSERT ON a BEGIN SELECT 1; END;\nSELECT * FROM b;\n")
+        .assert()
+        .success();
+    Ok(())
+}
+
+#[test]
+fn max_input_bytes_flag() -> Result<(), Box<dyn std::error::Error>> {
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--max-input-bytes", "20", "Select * From apples"])
+        .assert()
+        .success();
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--max-input-bytes", "19", "Select * From apples"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("exceeds the limit of 19 bytes"));
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--max-input-bytes", "20", "--file", "-"])
+        .with_stdin()
+        .buffer("SELECT * FROM a;\nSELECT * FROM b;\n")
+        .assert()
+        .code(4)
+        .stderr(predicates::str::contains("error: input of 34 bytes exceeds the limit of 20 bytes"))
+        .stderr(predicates::str::contains("failed to parse").not());
+    Ok(())
+}
+
+#[test]
+fn count_flag() -> Result<(), Box<dyn std::error::Error>> {
+    let sql_query = "Select * From main.apples Join apples Join pears Join (Select * From plums)";
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--count", sql_query])
+        .assert()
+        .success()
+        .stdout("4\n");
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--count", "--normalize-schema", sql_query])
+        .assert()
+        .success()
+        .stdout("3\n");
+    Ok(())
+}
+
+#[test]
+fn format_flag() -> Result<(), Box<dyn std::error::Error>> {
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--format", "json", "--sort", "Select * From pears Join apples"])
+        .assert()
+        .success()
+        .stdout("[\"apples\",\"pears\"]\n");
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--format", "yaml", "Select * From apples"])
+        .assert()
+        .failure();
+    Ok(())
+}
+
+#[test]
+fn exit_codes() -> Result<(), Box<dyn std::error::Error>> {
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["Select * From apples"])
+        .assert()
+        .code(0);
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["Select * From Where"])
+        .assert()
+        .code(2)
+        .stderr(predicates::str::contains("error: syntax error at line 1"));
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["PRAGMA foreign_keys = ON"])
+        .assert()
+        .code(0)
+        .stdout("\n");
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--fail-on-unsupported", "PRAGMA foreign_keys = ON"])
+        .assert()
+        .code(3)
+        .stderr(predicates::str::contains("error: unsupported PRAGMA statement"));
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--max-input-bytes", "1", "Select * From apples"])
+        .assert()
+        .code(4);
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--no-such-flag", "Select * From apples"])
+        .assert()
+        .code(1)
+        .stderr(predicates::str::contains("Unknown option --no-such-flag"));
+    Ok(())
+}
+
+#[test]
+fn scan_comment_hints_flag() -> Result<(), Box<dyn std::error::Error>> {
+    let sql_query = "SELECT /*+ table=foo route=bar */ * FROM apples";
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args([sql_query])
+        .assert()
+        .success()
+        .stdout("apples\n");
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--scan-comment-hints", "--sort", sql_query])
+        .assert()
+        .success()
+        .stdout("apples,foo\n");
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--hint-pattern", r"route=(\w+)", "--sort", sql_query])
+        .assert()
+        .success()
+        .stdout("apples,bar\n");
+    Ok(())
+}
+
+#[test]
+fn scan_comment_hints_normalized() -> Result<(), Box<dyn std::error::Error>> {
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--scan-comment-hints", "--normalize-case", "--format", "json", "SELECT /*+ table=ext.Bar */ * FROM ext.Bar"])
+        .assert()
+        .success()
+        .stdout("[\"ext\\u001fbar\"]\n");
+    Ok(())
+}
+
+#[test]
+fn ranked_flag() -> Result<(), Box<dyn std::error::Error>> {
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--ranked", "--file", "-"])
+        .with_stdin()
+        .buffer("SELECT * FROM b JOIN a;\nSELECT * FROM c;\nINSERT INTO b SELECT * FROM c;\nPRAGMA foreign_keys = ON;\nSELECT * FROM b;")
+        .assert()
+        .success()
+        .stdout("b\t3\nc\t2\na\t1\n");
+    Ok(())
+}
+
+#[test]
+fn explain_extraction_flag() -> Result<(), Box<dyn std::error::Error>> {
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--explain-extraction", "WITH c AS (SELECT * FROM apples) SELECT * FROM c"])
+        .assert()
+        .success()
+        .stdout("apples\n")
+        .stderr(predicates::str::contains("explain: statement 1: SELECT > WITH > FROM: added apples\n"))
+        .stderr(predicates::str::contains("explain: statement 1: SELECT > FROM: skipped c: CTE\n"));
+    Ok(())
+}
+
+#[test]
+fn join_count_flag() -> Result<(), Box<dyn std::error::Error>> {
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--join-count", "Select * From apples a JOIN pears p ON a.id = p.id, (SELECT * FROM b JOIN c)"])
+        .assert()
+        .success()
+        .stdout("3\n");
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--join-count", "SELECT * FROM a JOIN b; SELECT * FROM c; SELECT * FROM d JOIN e JOIN f"])
+        .assert()
+        .success()
+        .stdout("1\n0\n2\n");
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--join-count", "--file", "-"])
+        .with_stdin()
+        .buffer("SELECT * FROM a JOIN b;\nSELECT * FROM c, d, e;")
+        .assert()
+        .success()
+        .stdout("1\n2\n");
+    Ok(())
+}
+
+#[test]
+fn env_format_flag() -> Result<(), Box<dyn std::error::Error>> {
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--format", "env", "--var", "USED_TABLES", "--sort", "Select * From pears, apples"])
+        .assert()
+        .success()
+        .stdout("USED_TABLES=apples,pears\n");
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--format", "env", "--separator", " ", "--sort", "Select * From pears, apples"])
+        .assert()
+        .success()
+        .stdout("TABLES='apples pears'\n");
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--format", "env", "--var", "1X", "Select * From pears"])
+        .assert()
+        .code(1)
+        .stderr(predicates::str::contains("--var expects a shell variable name"));
+    Ok(())
+}
+
+#[test]
+fn filter_flag() -> Result<(), Box<dyn std::error::Error>> {
+    let sql_query = "INSERT INTO audit_log SELECT * FROM orders JOIN Audit_Users JOIN audit_events";
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--filter", "^audit_", "--sort", sql_query])
+        .assert()
+        .success()
+        .stdout("audit_events,audit_log\n");
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--filter", "^audit_", "--normalize-case", "--sort", sql_query])
+        .assert()
+        .success()
+        .stdout("audit_events,audit_log,audit_users\n");
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--filter", "^(?i:audit_)", "--count", sql_query])
+        .assert()
+        .success()
+        .stdout("3\n");
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--filter", "^nothing$", sql_query])
+        .assert()
+        .success()
+        .stdout("\n");
+    let sql_query = "SELECT * FROM ext.audit_log JOIN main.audit_users JOIN ext.orders JOIN audited";
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--filter", "^audit_", "--format", "lines", "--sort", sql_query])
+        .assert()
+        .success()
+        .stdout("ext\x1Faudit_log\nmain\x1Faudit_users\n");
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--filter", r"^ext\.", "--format", "lines", "--sort", sql_query])
+        .assert()
+        .success()
+        .stdout("ext\x1Faudit_log\next\x1Forders\n");
+    Ok(())
+}
+
+#[test]
+fn markdown_format_flag() -> Result<(), Box<dyn std::error::Error>> {
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--format", "markdown", "--sort", "--file", "-"])
+        .with_stdin()
+        .buffer("INSERT INTO pears SELECT * FROM apples;\nSELECT * FROM pears;")
+        .assert()
+        .success()
+        .stdout("| Schema | Table | Access |\n| --- | --- | --- |\n| main | apples | read |\n| main | pears | read/write |\n");
+    Ok(())
+}
+
+#[test]
+fn count_per_schema_flag() -> Result<(), Box<dyn std::error::Error>> {
+    let sql_query = "SELECT * FROM apples JOIN pears JOIN main.plums JOIN ext.figs JOIN ext.apples";
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--count-per-schema", sql_query])
+        .assert()
+        .success()
+        .stdout("main=3 ext=2\n");
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--count-per-schema", "--default-schema", "default", sql_query])
+        .assert()
+        .success()
+        .stdout("default=2 ext=2 main=1\n");
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--count-per-schema", "SELECT * FROM main.apples; SELECT * FROM apples"])
+        .assert()
+        .success()
+        .stdout("main=1\n");
+    Ok(())
+}
+
+#[test]
+fn strip_quotes_flag() -> Result<(), Box<dyn std::error::Error>> {
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--strip-quotes", "--sort", "Select * From `apples`\n JOIN \"pears\" ON 1\n JOIN [plums] ON 1"])
+        .assert()
+        .success()
+        .stdout("apples,pears,plums\n");
+    Ok(())
+}
+
+#[test]
+fn dedup_across_statements_flag() -> Result<(), Box<dyn std::error::Error>> {
+    let statements = "SELECT * FROM b JOIN a JOIN b;\nSELECT * FROM c;\nINSERT INTO b SELECT * FROM c;";
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--dedup-across-statements", "off", "--file", "-"])
+        .with_stdin()
+        .buffer(statements)
+        .assert()
+        .success()
+        .stdout("b,a,b,c,b,c\n");
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--dedup-across-statements", "off", statements])
+        .assert()
+        .success()
+        .stdout("b,a,b,c,b,c\n");
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--dedup-across-statements", "off", "SELECT * FROM a; SELECT * FROM a JOIN b"])
+        .assert()
+        .success()
+        .stdout("a,a,b\n");
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--dedup-across-statements", "on", "--sort", "--file", "-"])
+        .with_stdin()
+        .buffer(statements)
+        .assert()
+        .success()
+        .stdout("a,b,c\n");
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--format", "markdown", "--dedup-across-statements", "off", statements])
+        .assert()
+        .code(1)
+        .stderr(predicates::str::contains("--format markdown and --dedup-across-statements off can not be combined"));
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--count", "--ranked", statements])
+        .assert()
+        .code(1)
+        .stderr(predicates::str::contains("--count and --ranked can not be combined"));
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--count", "--format", "json", statements])
+        .assert()
+        .code(1)
+        .stderr(predicates::str::contains("--count and --format can not be combined"));
+    Command::cargo_bin("rust_sql_parser")
+        .expect("binary existst")
+        .args(["--join-count", "--var", "X", statements])
+        .assert()
+        .code(1)
+        .stderr(predicates::str::contains("--join-count and --var can not be combined"));
+    Ok(())
+}